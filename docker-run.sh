@@ -1,7 +1,7 @@
 #!/bin/bash
 
 if [[ ! -f ./songsdb/songs.db ]]; then
-  ./importer --db ./songsdb/songs.db -s 5 ./songs/
+  ./importer --db ./songsdb/songs.db ./songs/
 fi
 
 ./karaoke-server -c config.docker.yaml
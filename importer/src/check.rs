@@ -0,0 +1,60 @@
+use std::{
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Component, Path},
+};
+
+use rusqlite::Connection;
+
+/// The columns containing paths relative to the media root.
+const MEDIA_COLUMNS: [&str; 4] = ["audio_path", "cover_path", "video_path", "background_path"];
+
+/// Verifies that every media path stored in the database points to a file below `media_root`,
+/// so the web server can actually serve it.
+pub fn check_media_paths(conn: &Connection, media_root: &Path) -> anyhow::Result<()> {
+    let media_root = media_root.canonicalize()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT path, {} FROM song",
+        MEDIA_COLUMNS.join(", ")
+    ))?;
+    let mut rows = stmt.query(())?;
+
+    let mut checked = 0;
+    let mut broken = 0;
+    while let Some(row) = rows.next()? {
+        let txt_path = row.get::<_, Vec<u8>>("path")?;
+        for column in MEDIA_COLUMNS {
+            let Some(media_path) = row.get::<_, Option<Vec<u8>>>(column)? else {
+                continue;
+            };
+            checked += 1;
+            if let Err(err) = resolve(&media_root, Path::new(OsStr::from_bytes(&media_path))) {
+                broken += 1;
+                eprintln!(
+                    "{}: {column} {err}",
+                    Path::new(OsStr::from_bytes(&txt_path)).display()
+                );
+            }
+        }
+    }
+
+    println!("Checked {checked} media paths, {broken} don't resolve.");
+    if broken > 0 {
+        anyhow::bail!("{broken} media paths don't resolve below {media_root:?}");
+    }
+    Ok(())
+}
+
+fn resolve(media_root: &Path, media_path: &Path) -> anyhow::Result<()> {
+    if !media_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        anyhow::bail!("{media_path:?} is not a plain relative path");
+    }
+    let full_path = media_root.join(media_path);
+    if !full_path.is_file() {
+        anyhow::bail!("{full_path:?} doesn't exist");
+    }
+    Ok(())
+}
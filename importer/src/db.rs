@@ -0,0 +1,115 @@
+use std::{
+    collections::HashSet,
+    os::unix::ffi::OsStrExt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{named_params, Connection, OptionalExtension, Statement};
+
+use crate::scan::ParsedSong;
+
+/// Inserts a song, or updates it if its txt file is already in the database. Updates keep the
+/// time the song was added.
+pub const INSERT_SONG: &str = r#"INSERT INTO song (path, song_id, title, artist, language, language_codes, year, duration, lyrics, player_count, loudness, true_peak, lyrics_p1, lyrics_p2, singer_p1, singer_p2, genre, edition, creator, preview_start, medley_start_beat, medley_end_beat, bpm, gap, video_path, background_path, cover_path, cover_url, audio_path, lowest_pitch, highest_pitch, pitch_range, golden_share, freestyle_share, notes_per_second, txt_mtime, txt_size, txt_hash, txt_encoding, txt_encoding_source, audio_file, audio_mtime, audio_size, audio_hash, added_at)
+    VALUES (:path, :song_id, :title, :artist, :language, :language_codes, :year, :duration, :lyrics, :player_count, :loudness, :true_peak, :lyrics_p1, :lyrics_p2, :singer_p1, :singer_p2, :genre, :edition, :creator, :preview_start, :medley_start_beat, :medley_end_beat, :bpm, :gap, :video_path, :background_path, :cover_path, :cover_url, :audio_path, :lowest_pitch, :highest_pitch, :pitch_range, :golden_share, :freestyle_share, :notes_per_second, :txt_mtime, :txt_size, :txt_hash, :txt_encoding, :txt_encoding_source, :audio_file, :audio_mtime, :audio_size, :audio_hash, :added_at)
+    ON CONFLICT (path) DO UPDATE SET song_id=:song_id, title=:title, artist=:artist, language=:language, language_codes=:language_codes, year=:year, duration=:duration, lyrics=:lyrics, player_count=:player_count,
+    loudness=:loudness, true_peak=:true_peak,
+    lyrics_p1=:lyrics_p1, lyrics_p2=:lyrics_p2, singer_p1=:singer_p1, singer_p2=:singer_p2,
+    genre=:genre, edition=:edition, creator=:creator, preview_start=:preview_start, medley_start_beat=:medley_start_beat, medley_end_beat=:medley_end_beat, bpm=:bpm, gap=:gap, video_path=:video_path, background_path=:background_path, cover_path=:cover_path, cover_url=:cover_url, audio_path=:audio_path,
+    lowest_pitch=:lowest_pitch, highest_pitch=:highest_pitch, pitch_range=:pitch_range, golden_share=:golden_share, freestyle_share=:freestyle_share, notes_per_second=:notes_per_second,
+    txt_mtime=:txt_mtime, txt_size=:txt_size, txt_hash=:txt_hash, txt_encoding=:txt_encoding, txt_encoding_source=:txt_encoding_source, audio_file=:audio_file, audio_mtime=:audio_mtime, audio_size=:audio_size, audio_hash=:audio_hash"#;
+
+/// Executes [`INSERT_SONG`] for `song`, returning the number of changed rows.
+pub fn insert_song(stmt: &mut Statement<'_>, song: &ParsedSong) -> rusqlite::Result<usize> {
+    // Only used for new songs, updates keep the original time.
+    let added_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default();
+    stmt.execute(named_params! {
+        ":path": song.path.as_os_str().as_bytes(),
+        ":song_id": song.song_id,
+        ":title": song.title,
+        ":artist": song.artist,
+        ":language": song.language,
+        ":language_codes": (!song.language_codes.is_empty()).then(|| song.language_codes.join(",")),
+        ":year": song.year,
+        ":duration": song.duration,
+        ":lyrics": song.lyrics,
+        ":player_count": song.player_count,
+        ":loudness": song.loudness,
+        ":true_peak": song.true_peak,
+        ":lyrics_p1": song.lyrics_p1,
+        ":lyrics_p2": song.lyrics_p2,
+        ":singer_p1": song.singer_p1,
+        ":singer_p2": song.singer_p2,
+        ":genre": song.genre,
+        ":edition": song.edition,
+        ":creator": song.creator,
+        ":preview_start": song.preview_start,
+        ":medley_start_beat": song.medley_start_beat,
+        ":medley_end_beat": song.medley_end_beat,
+        ":bpm": song.bpm,
+        ":gap": song.gap,
+        ":video_path": song.video_path,
+        ":background_path": song.background_path,
+        ":cover_path": song.cover_path,
+        ":cover_url": song.cover_url,
+        ":audio_path": song.audio_path,
+        ":lowest_pitch": song.metrics.lowest_pitch,
+        ":highest_pitch": song.metrics.highest_pitch,
+        ":pitch_range": song.metrics.pitch_range,
+        ":golden_share": song.metrics.golden_share,
+        ":freestyle_share": song.metrics.freestyle_share,
+        ":notes_per_second": song.metrics.notes_per_second,
+        ":txt_mtime": song.txt.mtime,
+        ":txt_size": song.txt.size,
+        ":txt_hash": song.txt.hash,
+        ":txt_encoding": song.encoding.encoding.name(),
+        ":txt_encoding_source": song.encoding.source.as_str(),
+        ":audio_file": song.audio_file.as_os_str().as_bytes(),
+        ":audio_mtime": song.audio.mtime,
+        ":audio_size": song.audio.size,
+        ":audio_hash": song.audio.hash,
+        ":added_at": added_at,
+    })
+}
+
+/// Stores a setting, returning whether it had a different value before. Settings that weren't
+/// stored yet count as changed.
+pub fn update_setting(conn: &Connection, name: &str, value: &[u8]) -> rusqlite::Result<bool> {
+    let previous: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT value FROM setting WHERE name = ?1",
+            (name,),
+            |row| row.get(0),
+        )
+        .optional()?;
+    conn.execute(
+        "INSERT INTO setting (name, value) VALUES (?1, ?2) ON CONFLICT (name) DO UPDATE SET value = ?2",
+        (name, value),
+    )?;
+    Ok(previous.as_deref() != Some(value))
+}
+
+/// Returns the ids of all songs in the database.
+pub fn song_ids(conn: &Connection) -> rusqlite::Result<HashSet<String>> {
+    conn.prepare("SELECT song_id FROM song")?
+        .query_map((), |row| row.get(0))?
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_changed_settings() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        song_db::migrate(&mut conn).unwrap();
+        assert!(update_setting(&conn, "root", b"/songs").unwrap());
+        assert!(!update_setting(&conn, "root", b"/songs").unwrap());
+        assert!(update_setting(&conn, "root", b"/mnt/songs").unwrap());
+        assert!(update_setting(&conn, "media_root", b"/mnt/songs").unwrap());
+    }
+}
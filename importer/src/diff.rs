@@ -0,0 +1,189 @@
+use std::{os::unix::ffi::OsStrExt, path::Path};
+
+use rusqlite::{types::Value, OptionalExtension, Statement};
+use serde::Serialize;
+
+use crate::scan::ParsedSong;
+
+/// The changes an import makes to the database, collected in dry-run mode.
+#[derive(Debug, Default, Serialize)]
+pub struct Diff {
+    pub added: Vec<String>,
+    pub updated: Vec<UpdatedSong>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UpdatedSong {
+    pub path: String,
+    pub changes: Vec<FieldChange>,
+}
+
+/// A changed column. The values are omitted for the lyrics columns, since they're too long to be useful.
+#[derive(Debug, Serialize)]
+pub struct FieldChange {
+    pub field: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<String>,
+}
+
+/// The columns compared by [`Diff::update`].
+pub const COMPARED_FIELDS: [&str; 35] = [
+    "title",
+    "artist",
+    "language",
+    "language_codes",
+    "year",
+    "duration",
+    "lyrics",
+    "player_count",
+    "loudness",
+    "true_peak",
+    "lyrics_p1",
+    "lyrics_p2",
+    "singer_p1",
+    "singer_p2",
+    "genre",
+    "edition",
+    "creator",
+    "preview_start",
+    "medley_start_beat",
+    "medley_end_beat",
+    "bpm",
+    "gap",
+    "video_path",
+    "background_path",
+    "cover_path",
+    "cover_url",
+    "audio_path",
+    "lowest_pitch",
+    "highest_pitch",
+    "pitch_range",
+    "golden_share",
+    "freestyle_share",
+    "notes_per_second",
+    "txt_encoding",
+    "txt_encoding_source",
+];
+
+impl Diff {
+    pub fn add(&mut self, path: &Path) {
+        self.added.push(path.to_string_lossy().into_owned());
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.removed.push(path.to_string_lossy().into_owned());
+    }
+
+    /// Records the fields that differ between the database row of `song` and the newly parsed
+    /// version. `select_stmt` has to select [`COMPARED_FIELDS`] in order, for the path given as
+    /// the only parameter.
+    pub fn update(
+        &mut self,
+        select_stmt: &mut Statement<'_>,
+        song: &ParsedSong,
+    ) -> rusqlite::Result<()> {
+        let Some(old) = select_stmt
+            .query_row((song.path.as_os_str().as_bytes(),), |row| {
+                (0..COMPARED_FIELDS.len())
+                    .map(|idx| row.get::<_, Value>(idx))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .optional()?
+        else {
+            return Ok(());
+        };
+        let new = [
+            Value::from(song.title.clone()),
+            Value::from(song.artist.clone()),
+            Value::from(song.language.clone()),
+            Value::from((!song.language_codes.is_empty()).then(|| song.language_codes.join(","))),
+            Value::from(song.year),
+            Value::from(song.duration),
+            Value::from(song.lyrics.clone()),
+            Value::from(song.player_count),
+            Value::from(song.loudness),
+            Value::from(song.true_peak),
+            Value::from(song.lyrics_p1.clone()),
+            Value::from(song.lyrics_p2.clone()),
+            Value::from(song.singer_p1.clone()),
+            Value::from(song.singer_p2.clone()),
+            Value::from(song.genre.clone()),
+            Value::from(song.edition.clone()),
+            Value::from(song.creator.clone()),
+            Value::from(song.preview_start),
+            Value::from(song.medley_start_beat),
+            Value::from(song.medley_end_beat),
+            Value::from(song.bpm),
+            Value::from(song.gap),
+            Value::from(song.video_path.clone()),
+            Value::from(song.background_path.clone()),
+            Value::from(song.cover_path.clone()),
+            Value::from(song.cover_url.clone()),
+            Value::from(song.audio_path.clone()),
+            Value::from(song.metrics.lowest_pitch),
+            Value::from(song.metrics.highest_pitch),
+            Value::from(song.metrics.pitch_range),
+            Value::from(song.metrics.golden_share),
+            Value::from(song.metrics.freestyle_share),
+            Value::from(song.metrics.notes_per_second),
+            Value::from(song.encoding.encoding.name().to_owned()),
+            Value::from(song.encoding.source.as_str().to_owned()),
+        ];
+
+        let changes: Vec<_> = COMPARED_FIELDS
+            .into_iter()
+            .zip(old.iter().zip(new.iter()))
+            .filter(|(_, (old, new))| old != new)
+            .map(|(field, (old, new))| {
+                let show_values = !field.starts_with("lyrics");
+                FieldChange {
+                    field,
+                    old: show_values.then(|| display(old)),
+                    new: show_values.then(|| display(new)),
+                }
+            })
+            .collect();
+
+        if !changes.is_empty() {
+            self.updated.push(UpdatedSong {
+                path: song.path.to_string_lossy().into_owned(),
+                changes,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn print(&self) {
+        for path in &self.added {
+            println!("Would add {path}");
+        }
+        for song in &self.updated {
+            let changes = song
+                .changes
+                .iter()
+                .map(|change| match (&change.old, &change.new) {
+                    (Some(old), Some(new)) => format!("{}: {old:?} -> {new:?}", change.field),
+                    _ => change.field.to_owned(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!("Would update {} ({changes})", song.path);
+        }
+        for path in &self.removed {
+            println!("Would remove {path}");
+        }
+    }
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(value) => value.to_string(),
+        Value::Real(value) => value.to_string(),
+        Value::Text(value) => value.clone(),
+        Value::Blob(value) => String::from_utf8_lossy(value).into_owned(),
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use rusqlite::Transaction;
+
+/// How much the durations of two songs with the same artist and title may differ for them to
+/// count as the same song, in seconds.
+const DURATION_TOLERANCE: f64 = 3.0;
+
+/// Lowercases the text and drops everything that's not a letter or digit, so that differences in
+/// spelling like "AC/DC" vs "AC DC" or "Don't" vs "Dont" don't matter.
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// A minimal union-find over song indices.
+struct Groups(Vec<usize>);
+
+impl Groups {
+    fn find(&mut self, idx: usize) -> usize {
+        let mut root = idx;
+        while self.0[root] != root {
+            root = self.0[root];
+        }
+        // Path compression, so that later lookups are fast.
+        let mut idx = idx;
+        while self.0[idx] != root {
+            let next = self.0[idx];
+            self.0[idx] = root;
+            idx = next;
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.0[a.max(b)] = a.min(b);
+    }
+}
+
+/// Groups songs that are likely the same recording, typically from different song packs. Songs
+/// are duplicates if their audio files have the same content, or if they have the same normalized
+/// artist and title and their durations are within [`DURATION_TOLERANCE`] of the shortest one of
+/// them. Every group is
+/// identified by the lowest rowid of its songs, which is stored in the `duplicate_group` column
+/// (`NULL` for songs without duplicates).
+///
+/// Returns the number of groups and the number of songs in them.
+pub fn group_duplicates(tx: &Transaction<'_>) -> anyhow::Result<(usize, usize)> {
+    struct Row {
+        rowid: i64,
+        artist: String,
+        title: String,
+        duration: f64,
+        audio_hash: Option<String>,
+        duplicate_group: Option<i64>,
+    }
+
+    let rows: Vec<Row> = tx
+        .prepare("SELECT rowid, artist, title, duration, audio_hash, duplicate_group FROM song ORDER BY rowid")?
+        .query_map((), |row| {
+            Ok(Row {
+                rowid: row.get("rowid")?,
+                artist: row.get("artist")?,
+                title: row.get("title")?,
+                duration: row.get("duration")?,
+                audio_hash: row.get("audio_hash")?,
+                duplicate_group: row.get("duplicate_group")?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut groups = Groups((0..rows.len()).collect());
+
+    let mut by_hash = HashMap::new();
+    let mut by_name: HashMap<_, Vec<usize>> = HashMap::new();
+    for (idx, row) in rows.iter().enumerate() {
+        if let Some(hash) = &row.audio_hash {
+            if let Some(&other) = by_hash.get(hash) {
+                groups.union(idx, other);
+            } else {
+                by_hash.insert(hash, idx);
+            }
+        }
+        by_name
+            .entry((normalize(&row.artist), normalize(&row.title)))
+            .or_default()
+            .push(idx);
+    }
+    for mut songs in by_name.into_values() {
+        songs.sort_by(|&a, &b| rows[a].duration.total_cmp(&rows[b].duration));
+        // Songs are compared with the shortest song of their group rather than their neighbor,
+        // so that versions 3 s apart each don't chain up into one group spanning any duration.
+        let mut shortest: Option<usize> = None;
+        for idx in songs {
+            match shortest {
+                Some(first) if rows[idx].duration - rows[first].duration <= DURATION_TOLERANCE => {
+                    groups.union(first, idx)
+                }
+                _ => shortest = Some(idx),
+            }
+        }
+    }
+
+    // The rows are ordered by rowid and the root is always the lowest index, so the root's rowid
+    // is the lowest one in the group.
+    let mut sizes = vec![0; rows.len()];
+    for idx in 0..rows.len() {
+        sizes[groups.find(idx)] += 1;
+    }
+
+    let mut update_stmt = tx.prepare("UPDATE song SET duplicate_group = ?2 WHERE rowid = ?1")?;
+    let mut group_count = 0;
+    let mut song_count = 0;
+    for (idx, row) in rows.iter().enumerate() {
+        let root = groups.find(idx);
+        let duplicate_group = (sizes[root] > 1).then_some(rows[root].rowid);
+        if duplicate_group.is_some() {
+            song_count += 1;
+            if root == idx {
+                group_count += 1;
+            }
+        }
+        if duplicate_group != row.duplicate_group {
+            update_stmt.execute((row.rowid, duplicate_group))?;
+        }
+    }
+
+    Ok((group_count, song_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::*;
+
+    fn database(songs: &[(&str, &str, f64, Option<&str>)]) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        song_db::migrate(&mut conn).unwrap();
+        for (idx, (artist, title, duration, audio_hash)) in songs.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO song (path, song_id, artist, title, duration, audio_hash) VALUES (?1, ?1, ?2, ?3, ?4, ?5)",
+                (idx.to_string(), artist, title, duration, audio_hash),
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    fn duplicate_groups(conn: &mut Connection) -> ((usize, usize), Vec<Option<i64>>) {
+        let tx = conn.transaction().unwrap();
+        let counts = group_duplicates(&tx).unwrap();
+        let groups = tx
+            .prepare("SELECT duplicate_group FROM song ORDER BY rowid")
+            .unwrap()
+            .query_map((), |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        tx.commit().unwrap();
+        (counts, groups)
+    }
+
+    #[test]
+    fn groups_by_normalized_name_and_duration() {
+        let mut conn = database(&[
+            ("AC/DC", "Thunderstruck", 292.0, None),
+            ("ac dc", "thunderstruck", 294.5, None),
+            ("AC/DC", "Highway to Hell", 208.0, None),
+            ("AC/DC", "Thunderstruck", 310.0, None),
+        ]);
+        let (counts, groups) = duplicate_groups(&mut conn);
+        assert_eq!(counts, (1, 2));
+        assert_eq!(groups, [Some(1), Some(1), None, None]);
+    }
+
+    #[test]
+    fn durations_are_compared_with_the_shortest_version() {
+        let mut conn = database(&[
+            ("Queen", "Bohemian Rhapsody", 180.0, None),
+            ("Queen", "Bohemian Rhapsody", 183.0, None),
+            ("Queen", "Bohemian Rhapsody", 186.0, None),
+            ("Queen", "Bohemian Rhapsody", 188.0, None),
+        ]);
+        let (counts, groups) = duplicate_groups(&mut conn);
+        assert_eq!(counts, (2, 4));
+        assert_eq!(groups, [Some(1), Some(1), Some(3), Some(3)]);
+    }
+
+    #[test]
+    fn groups_identical_audio() {
+        let mut conn = database(&[
+            ("Unknown", "Track 1", 200.0, Some("abc")),
+            ("Queen", "Bohemian Rhapsody", 354.0, Some("abc")),
+            ("Queen", "Bohemian Rhapsody (Live)", 354.0, Some("def")),
+        ]);
+        let (counts, groups) = duplicate_groups(&mut conn);
+        assert_eq!(counts, (1, 2));
+        assert_eq!(groups, [Some(1), Some(1), None]);
+    }
+
+    #[test]
+    fn regrouping_clears_old_groups() {
+        let mut conn = database(&[
+            ("Queen", "Bohemian Rhapsody", 354.0, None),
+            ("Queen", "Bohemian Rhapsody", 355.0, None),
+        ]);
+        duplicate_groups(&mut conn);
+        conn.execute("DELETE FROM song WHERE rowid = 2", ())
+            .unwrap();
+        let (counts, groups) = duplicate_groups(&mut conn);
+        assert_eq!(counts, (0, 0));
+        assert_eq!(groups, [None]);
+    }
+}
@@ -0,0 +1,86 @@
+use isolang::Language;
+
+/// Common names that differ from the ISO 639 names, mapped to ISO 639-1 codes.
+const ALIASES: [(&str, &str); 3] = [("greek", "el"), ("farsi", "fa"), ("flemish", "nl")];
+
+/// Splits the free text of a `#LANGUAGE` tag into languages and maps them to ISO 639 codes,
+/// dropping duplicates. Returns the codes along with the parts that aren't a known language.
+///
+/// Languages are recognized by their English name, their own name (like "Deutsch") or their
+/// ISO 639-1 or 639-3 code, ignoring case. The codes are ISO 639-1 if the language has one, and
+/// ISO 639-3 otherwise, the same as in locale identifiers.
+pub fn normalize(language: &str) -> (Vec<&'static str>, Vec<String>) {
+    let mut codes = Vec::new();
+    let mut unknown = Vec::new();
+    for part in language
+        .split([',', ';', '/', '&', '+'])
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        match lookup(part) {
+            Some(language) => {
+                let code = language.to_639_1().unwrap_or_else(|| language.to_639_3());
+                if !codes.contains(&code) {
+                    codes.push(code);
+                }
+            }
+            None => unknown.push(part.to_owned()),
+        }
+    }
+    (codes, unknown)
+}
+
+fn lookup(name: &str) -> Option<Language> {
+    let lowercase = name.to_lowercase();
+    match lowercase.len() {
+        2 => Language::from_639_1(&lowercase),
+        3 => Language::from_639_3(&lowercase),
+        _ => None,
+    }
+    .or_else(|| {
+        ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == lowercase)
+            .and_then(|(_, code)| Language::from_639_1(code))
+    })
+    .or_else(|| Language::from_name_lowercase(&lowercase))
+    .or_else(|| Language::match_autonyms(move |autonym| autonym.to_lowercase() == lowercase).next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_names_and_codes() {
+        assert_eq!(normalize("English"), (vec!["en"], vec![]));
+        assert_eq!(normalize("german"), (vec!["de"], vec![]));
+        assert_eq!(normalize("Deutsch"), (vec!["de"], vec![]));
+        assert_eq!(normalize("FR"), (vec!["fr"], vec![]));
+        assert_eq!(normalize("spa"), (vec!["es"], vec![]));
+        assert_eq!(normalize("Greek"), (vec!["el"], vec![]));
+    }
+
+    #[test]
+    fn falls_back_to_iso_639_3() {
+        // Cantonese has no ISO 639-1 code.
+        assert_eq!(normalize("yue"), (vec!["yue"], vec![]));
+    }
+
+    #[test]
+    fn splits_multiple_languages() {
+        assert_eq!(
+            normalize("English, Spanish / english;  & French+"),
+            (vec!["en", "es", "fr"], vec![])
+        );
+    }
+
+    #[test]
+    fn reports_unknown_languages() {
+        assert_eq!(
+            normalize("English, Elvish"),
+            (vec!["en"], vec!["Elvish".to_owned()])
+        );
+        assert_eq!(normalize(" "), (vec![], vec![]));
+    }
+}
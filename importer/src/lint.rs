@@ -0,0 +1,468 @@
+use std::{
+    fs::{self, read_dir, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rayon::prelude::*;
+use serde::Serialize;
+use ultrastar_txt::{Line, Note, Source, TXTSong};
+
+use crate::{report::ReportFormat, txt};
+
+/// The earliest year a recording can be from.
+const FIRST_RECORDING_YEAR: u32 = 1877;
+
+#[derive(clap::Args, Debug)]
+pub struct LintArgs {
+    /// The song collection to check, or a single txt file.
+    path: PathBuf,
+
+    /// Write the warnings to this file, instead of printing them.
+    #[clap(short, long)]
+    report: Option<PathBuf>,
+
+    /// The file format of the report.
+    #[clap(long, value_enum, default_value_t = ReportFormat::Json)]
+    report_format: ReportFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintCheck {
+    /// The txt file couldn't be read or parsed at all.
+    ParseError,
+    /// The audio file doesn't exist or can't be opened.
+    MissingAudio,
+    /// The audio ends before the last note does.
+    AudioTooShort,
+    /// Notes of the same voice overlap.
+    OverlappingNotes,
+    MissingGap,
+    /// The cover tag points to a file that doesn't exist.
+    MissingCover,
+    EmptyTitle,
+    EmptyArtist,
+    /// The year is before the first recording or in the future.
+    InvalidYear,
+    /// Some lines are UTF-8, others use a different encoding.
+    MixedEncodings,
+    /// The song is marked up as a duet, but the second voice doesn't sing anything.
+    DuetWithoutP2,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LintWarning {
+    pub check: LintCheck,
+    pub message: String,
+}
+
+impl LintWarning {
+    fn new(check: LintCheck, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LintedFile {
+    pub path: String,
+    pub warnings: Vec<LintWarning>,
+}
+
+/// Checks all txt files below `args.path` and lists the problems per file.
+pub fn lint(args: &LintArgs) -> anyhow::Result<()> {
+    let mut txt_files = Vec::new();
+    collect_txt_files(&args.path, &mut txt_files)?;
+
+    let mut files: Vec<_> = txt_files
+        .par_iter()
+        .map(|path| LintedFile {
+            path: path.to_string_lossy().into_owned(),
+            warnings: lint_file(path),
+        })
+        .filter(|file| !file.warnings.is_empty())
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    if let Some(report_path) = &args.report {
+        write_report(&files, report_path, args.report_format)?;
+    } else {
+        for file in &files {
+            println!("{}", file.path);
+            for warning in &file.warnings {
+                println!("  - {}", warning.message);
+            }
+        }
+    }
+
+    println!(
+        "{} of {} files have problems.",
+        files.len(),
+        txt_files.len()
+    );
+    Ok(())
+}
+
+fn collect_txt_files(path: &Path, txt_files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    if path.is_dir() {
+        for entry in read_dir(path)? {
+            collect_txt_files(&entry?.path(), txt_files)?;
+        }
+    } else if path.extension().is_some_and(|ext| ext == "txt") {
+        txt_files.push(path.to_owned());
+    }
+    Ok(())
+}
+
+fn write_report(files: &[LintedFile], path: &Path, format: ReportFormat) -> anyhow::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    match format {
+        ReportFormat::Json => serde_json::to_writer_pretty(file, files)?,
+        ReportFormat::Csv => {
+            #[derive(Serialize)]
+            struct Row<'a> {
+                path: &'a str,
+                check: LintCheck,
+                message: &'a str,
+            }
+
+            let mut writer = csv::Writer::from_writer(file);
+            for file in files {
+                for warning in &file.warnings {
+                    writer.serialize(Row {
+                        path: &file.path,
+                        check: warning.check,
+                        message: &warning.message,
+                    })?;
+                }
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn lint_file(path: &Path) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+
+    match fs::read(path) {
+        Ok(bytes) => check_encoding(&bytes, &mut warnings),
+        Err(err) => {
+            warnings.push(LintWarning::new(LintCheck::ParseError, err.to_string()));
+            return warnings;
+        }
+    }
+
+    let song = match txt::load_song(path) {
+        Ok((song, _)) => song,
+        Err(err) => {
+            warnings.push(LintWarning::new(LintCheck::ParseError, format!("{err:#}")));
+            return warnings;
+        }
+    };
+    let header = &song.header;
+
+    if header.title.trim().is_empty() {
+        warnings.push(LintWarning::new(
+            LintCheck::EmptyTitle,
+            "The title is empty.",
+        ));
+    }
+    if header.artist.trim().is_empty() {
+        warnings.push(LintWarning::new(
+            LintCheck::EmptyArtist,
+            "The artist is empty.",
+        ));
+    }
+    if let Some(year) = header.year {
+        check_year(year, &mut warnings);
+    }
+    if header.gap.is_none() {
+        warnings.push(LintWarning::new(
+            LintCheck::MissingGap,
+            "The #GAP tag is missing.",
+        ));
+    }
+    if let Some(Source::Local(cover_path)) = &header.cover_path {
+        if !cover_path.exists() {
+            warnings.push(LintWarning::new(
+                LintCheck::MissingCover,
+                format!("The cover {cover_path:?} doesn't exist."),
+            ));
+        }
+    }
+
+    let notes = voice_notes(&song);
+    check_overlaps(&notes, &mut warnings);
+    check_duet(&song.lines, &notes, &mut warnings);
+    check_audio_duration(&song, &notes, &mut warnings);
+
+    warnings
+}
+
+/// Files are supposed to be UTF-8 or a single legacy encoding, but files edited with different
+/// tools sometimes end up with both.
+fn check_encoding(bytes: &[u8], warnings: &mut Vec<LintWarning>) {
+    let mut has_utf8 = false;
+    let mut has_other = false;
+    for chunk in bytes.utf8_chunks() {
+        has_utf8 |= !chunk.valid().is_ascii();
+        has_other |= !chunk.invalid().is_empty();
+    }
+    if has_utf8 && has_other {
+        warnings.push(LintWarning::new(
+            LintCheck::MixedEncodings,
+            "The file contains both UTF-8 and non-UTF-8 text.",
+        ));
+    }
+}
+
+fn check_year(year: u32, warnings: &mut Vec<LintWarning>) {
+    if !(FIRST_RECORDING_YEAR..=current_year()).contains(&year) {
+        warnings.push(LintWarning::new(
+            LintCheck::InvalidYear,
+            format!("{year} is not a valid year."),
+        ));
+    }
+}
+
+/// A sung note in absolute beats.
+struct VoiceNote {
+    /// 1 or 2, notes sung by both players are listed for each of them.
+    player: i32,
+    start: i32,
+    end: i32,
+}
+
+/// Converts the notes to absolute beats (resolving relative mode) and assigns them to voices.
+fn voice_notes(song: &TXTSong) -> Vec<VoiceNote> {
+    let relative = song.header.relative.unwrap_or_default();
+    let mut notes = Vec::new();
+    let mut offset = 0;
+    let mut player = 1;
+    for line in &song.lines {
+        if relative {
+            offset += line.rel.unwrap_or_default();
+        }
+        for note in &line.notes {
+            match note {
+                Note::Regular {
+                    start, duration, ..
+                }
+                | Note::Golden {
+                    start, duration, ..
+                }
+                | Note::Freestyle {
+                    start, duration, ..
+                } => {
+                    for voice in [1, 2] {
+                        if player == voice || player == 3 {
+                            notes.push(VoiceNote {
+                                player: voice,
+                                start: offset + start,
+                                end: offset + start + duration,
+                            });
+                        }
+                    }
+                }
+                Note::PlayerChange { player: next } => {
+                    player = *next;
+                    // The beats of every voice start at the beginning of the song.
+                    offset = 0;
+                }
+            }
+        }
+    }
+    notes
+}
+
+fn check_overlaps(notes: &[VoiceNote], warnings: &mut Vec<LintWarning>) {
+    for voice in [1, 2] {
+        let mut previous_end = i32::MIN;
+        let mut overlaps = Vec::new();
+        for note in notes.iter().filter(|note| note.player == voice) {
+            if note.start < previous_end {
+                overlaps.push(note.start);
+            }
+            previous_end = previous_end.max(note.end);
+        }
+        if let Some(first) = overlaps.first() {
+            warnings.push(LintWarning::new(
+                LintCheck::OverlappingNotes,
+                format!(
+                    "{} notes of P{voice} overlap with the previous note, the first one at beat {first}.",
+                    overlaps.len()
+                ),
+            ));
+        }
+    }
+}
+
+fn check_duet(lines: &[Line], notes: &[VoiceNote], warnings: &mut Vec<LintWarning>) {
+    let is_duet = lines
+        .iter()
+        .flat_map(|line| &line.notes)
+        .any(|note| matches!(note, Note::PlayerChange { .. }));
+    if is_duet && !notes.iter().any(|note| note.player == 2) {
+        warnings.push(LintWarning::new(
+            LintCheck::DuetWithoutP2,
+            "The song is a duet, but P2 has no notes.",
+        ));
+    }
+}
+
+fn check_audio_duration(song: &TXTSong, notes: &[VoiceNote], warnings: &mut Vec<LintWarning>) {
+    let Source::Local(audio_path) = &song.header.audio_path else {
+        return;
+    };
+    let duration = ffmpeg_next::format::input(audio_path)
+        .ok()
+        .and_then(|context| {
+            let stream = context.streams().best(ffmpeg_next::media::Type::Audio)?;
+            Some(stream.duration() as f64 * f64::from(stream.time_base()))
+        });
+    let Some(duration) = duration else {
+        warnings.push(LintWarning::new(
+            LintCheck::MissingAudio,
+            format!("The audio file {audio_path:?} can't be opened."),
+        ));
+        return;
+    };
+
+    let bpm = f64::from(song.header.bpm);
+    let Some(last_beat) = notes.iter().map(|note| note.end).max() else {
+        return;
+    };
+    if bpm <= 0.0 {
+        return;
+    }
+    // Beats are quarter notes.
+    let last_note_end = f64::from(song.header.gap.unwrap_or_default()) / 1000.0
+        + f64::from(last_beat) * 60.0 / (bpm * 4.0);
+    if duration < last_note_end {
+        warnings.push(LintWarning::new(
+            LintCheck::AudioTooShort,
+            format!(
+                "The audio is {duration:.1}s long, but the last note ends at {last_note_end:.1}s."
+            ),
+        ));
+    }
+}
+
+fn current_year() -> u32 {
+    const SECONDS_PER_YEAR: u64 = 31_556_952;
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    1970 + (seconds / SECONDS_PER_YEAR) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> TXTSong {
+        TXTSong {
+            header: ultrastar_txt::parse_txt_header_str(text).unwrap(),
+            lines: ultrastar_txt::parse_txt_lines_str(text).unwrap(),
+        }
+    }
+
+    /// Runs the note checks on a song.
+    fn note_checks(text: &str) -> Vec<LintCheck> {
+        let song = parse(text);
+        let notes = voice_notes(&song);
+        let mut warnings = Vec::new();
+        check_overlaps(&notes, &mut warnings);
+        check_duet(&song.lines, &notes, &mut warnings);
+        warnings.into_iter().map(|warning| warning.check).collect()
+    }
+
+    fn encoding_checks(bytes: &[u8]) -> Vec<LintCheck> {
+        let mut warnings = Vec::new();
+        check_encoding(bytes, &mut warnings);
+        warnings.into_iter().map(|warning| warning.check).collect()
+    }
+
+    fn year_checks(year: u32) -> Vec<LintCheck> {
+        let mut warnings = Vec::new();
+        check_year(year, &mut warnings);
+        warnings.into_iter().map(|warning| warning.check).collect()
+    }
+
+    const HEADER: &str = "#TITLE:Song\n#ARTIST:Artist\n#MP3:song.mp3\n#BPM:300\n#GAP:1000\n";
+
+    #[test]
+    fn accepts_a_single_encoding() {
+        assert!(encoding_checks("#TITLE:Ça ira\n".as_bytes()).is_empty());
+        assert!(encoding_checks(b"#TITLE:\xc7a ira\n").is_empty());
+        assert!(encoding_checks(b"#TITLE:Song\n").is_empty());
+    }
+
+    #[test]
+    fn detects_mixed_encodings() {
+        let bytes = b"#TITLE:\xc3\x87a ira\n#ARTIST:Andr\xe9\n";
+        assert_eq!(encoding_checks(bytes), [LintCheck::MixedEncodings]);
+    }
+
+    #[test]
+    fn accepts_notes_in_sequence() {
+        let text = format!("{HEADER}: 0 4 5 Hel\n: 4 4 5 lo\n- 10\n* 12 2 7 world\nE\n");
+        assert!(note_checks(&text).is_empty());
+    }
+
+    #[test]
+    fn detects_overlapping_notes() {
+        let text = format!("{HEADER}: 0 4 5 Hel\n: 2 4 5 lo\n- 10\n: 12 2 7 world\nE\n");
+        assert_eq!(note_checks(&text), [LintCheck::OverlappingNotes]);
+    }
+
+    #[test]
+    fn resolves_relative_beats() {
+        let text =
+            format!("{HEADER}#RELATIVE:YES\n: 0 4 5 Hel\n: 4 4 5 lo\n- 8 8\n: 0 4 7 world\nE\n");
+        assert!(note_checks(&text).is_empty());
+    }
+
+    #[test]
+    fn accepts_voices_singing_at_the_same_time() {
+        let text = format!(
+            "{HEADER}P1\n: 0 4 5 Hel\n: 4 4 5 lo\nP2\n: 0 4 5 Hel\n: 4 4 5 lo\nP3\n: 10 4 5 both\nE\n"
+        );
+        assert!(note_checks(&text).is_empty());
+    }
+
+    #[test]
+    fn detects_overlaps_with_notes_sung_by_both() {
+        let text = format!("{HEADER}P1\n: 0 4 5 Hel\nP2\n: 10 4 5 lo\nP3\n: 11 4 5 both\nE\n");
+        assert_eq!(note_checks(&text), [LintCheck::OverlappingNotes]);
+    }
+
+    #[test]
+    fn detects_duets_without_p2() {
+        let text = format!("{HEADER}P1\n: 0 4 5 Hel\n: 4 4 5 lo\nP2\nE\n");
+        assert_eq!(note_checks(&text), [LintCheck::DuetWithoutP2]);
+    }
+
+    #[test]
+    fn accepts_years_since_the_first_recording() {
+        assert!(year_checks(FIRST_RECORDING_YEAR).is_empty());
+        assert!(year_checks(1985).is_empty());
+        assert!(year_checks(current_year()).is_empty());
+    }
+
+    #[test]
+    fn detects_invalid_years() {
+        assert_eq!(year_checks(0), [LintCheck::InvalidYear]);
+        assert_eq!(
+            year_checks(FIRST_RECORDING_YEAR - 1),
+            [LintCheck::InvalidYear]
+        );
+        assert_eq!(year_checks(current_year() + 1), [LintCheck::InvalidYear]);
+    }
+}
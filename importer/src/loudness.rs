@@ -0,0 +1,89 @@
+use anyhow::Context as _;
+use ebur128::{EbuR128, Mode};
+use ffmpeg_next::{
+    codec, decoder,
+    format::{context::Input, sample::Type, Sample},
+    frame,
+    software::resampling,
+    ChannelLayout,
+};
+
+/// The loudness of a song's audio according to EBU R128, for normalizing the playback volume.
+#[derive(Debug, Clone, Copy)]
+pub struct Loudness {
+    /// Integrated loudness, in LUFS.
+    pub integrated: f64,
+    /// The highest true peak of all channels, in dBTP.
+    pub true_peak: f64,
+}
+
+/// Decodes the whole audio stream and measures its loudness. Returns `None` for silent audio.
+pub fn analyze(input: &mut Input, stream_index: usize) -> anyhow::Result<Option<Loudness>> {
+    let stream = input
+        .stream(stream_index)
+        .context("The audio stream disappeared")?;
+    let mut decoder = codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .audio()?;
+
+    // Some formats don't specify the layout, assume the default one for the channel count then.
+    let channel_layout = if decoder.channel_layout().is_empty() {
+        ChannelLayout::default(decoder.channels() as _)
+    } else {
+        decoder.channel_layout()
+    };
+    decoder.set_channel_layout(channel_layout);
+
+    // The meter needs floating point samples, but keeps the sample rate.
+    let mut resampler = resampling::Context::get(
+        decoder.format(),
+        channel_layout,
+        decoder.rate(),
+        Sample::F32(Type::Planar),
+        channel_layout,
+        decoder.rate(),
+    )?;
+    let channels = channel_layout.channels() as u32;
+    let mut meter = EbuR128::new(channels, decoder.rate(), Mode::I | Mode::TRUE_PEAK)?;
+
+    for (stream, packet) in input.packets() {
+        if stream.index() == stream_index {
+            decoder.send_packet(&packet)?;
+            measure_frames(&mut decoder, &mut resampler, &mut meter)?;
+        }
+    }
+    decoder.send_eof()?;
+    measure_frames(&mut decoder, &mut resampler, &mut meter)?;
+
+    let integrated = meter.loudness_global()?;
+    if !integrated.is_finite() {
+        return Ok(None);
+    }
+    let mut true_peak = 0.0f64;
+    for channel in 0..channels {
+        true_peak = true_peak.max(meter.true_peak(channel)?);
+    }
+
+    Ok(Some(Loudness {
+        integrated,
+        true_peak: 20.0 * true_peak.log10(),
+    }))
+}
+
+/// Feeds all frames the decoder has ready into the meter.
+fn measure_frames(
+    decoder: &mut decoder::Audio,
+    resampler: &mut resampling::Context,
+    meter: &mut EbuR128,
+) -> anyhow::Result<()> {
+    let mut decoded = frame::Audio::empty();
+    let mut resampled = frame::Audio::empty();
+    while decoder.receive_frame(&mut decoded).is_ok() {
+        resampler.run(&decoded, &mut resampled)?;
+        let planes: Vec<&[f32]> = (0..resampled.planes())
+            .map(|plane| &resampled.plane::<f32>(plane)[..resampled.samples()])
+            .collect();
+        meter.add_frames_planar_f32(&planes)?;
+    }
+    Ok(())
+}
@@ -1,225 +1,358 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     ffi::OsStr,
-    fs::{metadata, read_dir},
+    fs,
     os::unix::ffi::OsStrExt,
-    path::{Path, PathBuf},
+    path::PathBuf,
+    sync::mpsc::sync_channel,
+    thread,
 };
 
-use clap::Parser;
-use rusqlite::{Connection, OpenFlags, Statement};
-use ultrastar_txt::{loader, Source};
+use clap::{Parser, Subcommand};
+use rusqlite::{Connection, OpenFlags};
+
+use crate::{
+    diff::{Diff, COMPARED_FIELDS},
+    report::{Categorize, Failure, FailureCategory, Report, ReportFormat},
+    scan::{ExistingSong, FileState, Scanned, Scanner},
+};
+
+mod check;
+mod db;
+mod diff;
+mod duplicates;
+mod language;
+mod lint;
+mod loudness;
+mod metrics;
+mod preview;
+mod report;
+mod scan;
+mod txt;
+mod watch;
 
 #[derive(Parser, Debug)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    path: PathBuf,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// The song collection to import.
+    #[clap(required_unless_present = "check")]
+    path: Option<PathBuf>,
 
     /// The path to the sqlite database to write the output to. Will be created if it doesn't exist.
+    #[clap(short, long, required = true)]
+    db: Option<PathBuf>,
+
+    /// The directory served as `paths.media` by the web server. Media paths are stored relative to
+    /// it. Defaults to the song collection.
     #[clap(short, long)]
-    db: PathBuf,
+    media_root: Option<PathBuf>,
 
-    /// How many path components to remove from media paths to match the web server configuration.
-    #[clap(short, long, default_value_t = 0)]
-    strip_components: usize,
-}
+    /// Re-parse all songs, even those whose files haven't changed since the last import.
+    #[clap(long)]
+    full: bool,
 
-fn parse_txt(
-    path: impl AsRef<Path>,
-    strip_components: usize,
-    insert_stmt: &mut Statement<'_>,
-    inserted_set: &mut HashSet<PathBuf>,
-) -> anyhow::Result<()> {
-    let full_path = path.as_ref().canonicalize()?;
-    let song = loader::parse_txt_song(&path).map_err(|err| anyhow::anyhow!("{err:?}"))?;
-
-    let Source::Local(audio_path) = &song.header.audio_path else {
-        return Err(anyhow::anyhow!(
-            "{:?} does not have a local audio track.",
-            path.as_ref()
-        ));
-    };
+    /// How many songs to parse and probe in parallel. Defaults to the number of CPU cores.
+    #[clap(short, long)]
+    jobs: Option<usize>,
 
-    let context = ffmpeg_next::format::input(&audio_path)?;
-    let Some(stream) = context.streams().best(ffmpeg_next::media::Type::Audio) else {
-        return Err(anyhow::anyhow!(
-            "{:?} does not contain an audio track.",
-            path.as_ref()
-        ));
-    };
+    /// Write a report of all skipped songs and a summary of the changes to this file.
+    #[clap(short, long)]
+    report: Option<PathBuf>,
 
-    let cover_path = song.header.cover_path.map(|cover_path| match cover_path {
-        Source::Local(cover_path) => cover_path
-            .components()
-            .skip(strip_components)
-            .collect::<PathBuf>()
-            .as_os_str()
-            .as_bytes()
-            .to_owned(),
-        _ => panic!("Song {} has remote cover", song.header.title),
-    });
-
-    let audio_path = match &song.header.audio_path {
-        Source::Local(audio_path) => audio_path
-            .components()
-            .skip(strip_components)
-            .collect::<PathBuf>()
-            .as_os_str()
-            .as_bytes()
-            .to_owned(),
-        _ => panic!("Song {} has remote audio", song.header.title),
-    };
+    /// The file format of the report.
+    #[clap(long, value_enum, default_value_t = ReportFormat::Json)]
+    report_format: ReportFormat,
 
-    let player_count = (song.lines.iter().any(|line| {
-        line.notes.iter().any(|note| match note {
-            ultrastar_txt::Note::PlayerChange { player } => *player == 2,
-            _ => false,
-        })
-    }) as u32)
-        + 1;
-
-    let changes = insert_stmt.execute((
-        full_path.as_os_str().as_bytes(),
-        song.header.title.trim(),
-        song.header.artist.trim(),
-        song.header.language.map(|lang| lang.trim().to_owned()),
-        song.header.year,
-        stream.duration() as f64 * f64::from(stream.time_base()),
-        song.lines
-            .into_iter()
-            .map(|line| {
-                line.notes
-                    .into_iter()
-                    .filter_map(|note| match note {
-                        ultrastar_txt::Note::Regular { text, .. } => Some(text),
-                        ultrastar_txt::Note::Golden { text, .. } => Some(text),
-                        ultrastar_txt::Note::Freestyle { text, .. } => Some(text),
-                        ultrastar_txt::Note::PlayerChange { .. } => None,
-                    })
-                    .collect::<String>()
-                    .trim()
-                    .to_owned()
-            })
-            .collect::<Vec<_>>()
-            .join("\n"),
-        player_count,
-        cover_path,
-        audio_path,
-    ))?;
-
-    if changes == 1 {
-        inserted_set.insert(full_path);
-    } else {
-        eprintln!("{full_path:?}: Failed inserting into database");
-    }
+    /// Only show which songs would be added, updated and removed, without changing the database.
+    /// The database has to exist already.
+    #[clap(long)]
+    dry_run: bool,
 
-    Ok(())
-}
+    /// Don't import anything, but verify that all media paths in the database resolve to files
+    /// below the media root.
+    #[clap(long, requires = "media_root")]
+    check: bool,
 
-fn walk_dir(
-    path: impl AsRef<Path>,
-    strip_components: usize,
-    insert_stmt: &mut Statement<'_>,
-    inserted_set: &mut HashSet<PathBuf>,
-) -> anyhow::Result<()> {
-    for subdir in read_dir(path)? {
-        let subdir = subdir?;
-
-        // Retrieve metadata based on the actual file or symlink.
-        let file_type = subdir.file_type()?;
-
-        if file_type.is_dir() || (file_type.is_symlink() && metadata(subdir.path())?.is_dir()) {
-            // Recursive call if it's a directory or a symlink pointing to a directory
-            walk_dir(subdir.path(), strip_components, insert_stmt, inserted_set)?;
-        } else if file_type.is_file() {
-            // Handle files, specifically .txt files.
-            let file_path = subdir.path();
-            if let Some(ext) = file_path.extension() {
-                if ext == "txt" {
-                    // Process txt file
-                    if let Err(err) =
-                        parse_txt(&file_path, strip_components, insert_stmt, inserted_set)
-                    {
-                        eprintln!("{err}");
-                    }
-                }
-            }
-        }
-    }
+    /// Cut a short preview clip of every song into this directory, which has to be configured as
+    /// `paths.previews` for the server. Not done in dry-run mode.
+    #[clap(short, long)]
+    previews: Option<PathBuf>,
 
-    Ok(())
+    /// After importing, keep watching the song collection and import changes as they happen.
+    #[clap(short, long, conflicts_with_all = ["dry_run", "check"])]
+    watch: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check UltraStar files for common problems, without importing them.
+    Lint(lint::LintArgs),
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
+    if let Some(Command::Lint(lint_args)) = &args.command {
+        ffmpeg_next::init()?;
+        ffmpeg_next::log::set_level(ffmpeg_next::log::Level::Fatal);
+        return lint::lint(lint_args);
+    }
+    let db = args.db.expect("the database is required unless linting");
+
+    if args.check {
+        let conn = Connection::open_with_flags(db, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        song_db::check_schema_version(&conn)?;
+        return check::check_media_paths(&conn, args.media_root.as_deref().unwrap());
+    }
+    let path = args.path.expect("the path is required unless checking");
+    let root = path.canonicalize()?;
+    let media_root = args.media_root.as_ref().unwrap_or(&path).canonicalize()?;
+
     ffmpeg_next::init()?;
     ffmpeg_next::log::set_level(ffmpeg_next::log::Level::Fatal);
 
-    let mut conn = Connection::open_with_flags(
-        args.db,
-        OpenFlags::SQLITE_OPEN_CREATE | OpenFlags::SQLITE_OPEN_READ_WRITE,
-    )
-    .unwrap();
-    conn.execute(
-        r#"CREATE TABLE IF NOT EXISTS song (
-        path BLOB UNIQUE NOT NULL,
-        title TEXT NOT NULL,
-        artist TEXT NOT NULL,
-        language TEXT,
-        year INTEGER,
-        duration REAL NOT NULL,
-        lyrics TEXT,
-        player_count INTEGER,
-        cover_path BLOB,
-        audio_path BLOB
-    )"#,
-        (),
-    )?;
+    let mut conn = if args.dry_run {
+        Connection::open_with_flags(db, OpenFlags::SQLITE_OPEN_READ_WRITE)?
+    } else {
+        let mut conn = Connection::open_with_flags(
+            db,
+            OpenFlags::SQLITE_OPEN_CREATE | OpenFlags::SQLITE_OPEN_READ_WRITE,
+        )
+        .unwrap();
+        song_db::migrate(&mut conn)?;
+        conn
+    };
+
+    let previews = match &args.previews {
+        Some(previews) if !args.dry_run => {
+            fs::create_dir_all(previews)?;
+            Some(previews.canonicalize()?)
+        }
+        _ => None,
+    };
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or(0))
+        .build()?;
 
     let tx = conn.transaction()?;
+    if args.dry_run {
+        // Migrating is rolled back together with the import.
+        song_db::migrate_in_transaction(&tx)?;
+    }
     {
-        let existing_songs: HashSet<_> = tx
-            .prepare("SELECT path FROM song")?
+        let existing_songs: HashMap<_, _> = tx
+            .prepare("SELECT path, txt_mtime, txt_size, txt_hash, audio_file, audio_mtime, audio_size, audio_hash FROM song")?
             .query_map((), |row| {
-                row.get::<_, Vec<u8>>(0)
-                    .map(|bytes| PathBuf::from(OsStr::from_bytes(&bytes)))
+                let file_state = |mtime, size, hash| -> rusqlite::Result<_> {
+                    Ok(match (row.get(mtime)?, row.get(size)?, row.get(hash)?) {
+                        (Some(mtime), Some(size), Some(hash)) => Some(FileState { mtime, size, hash }),
+                        _ => None,
+                    })
+                };
+                Ok((
+                    PathBuf::from(OsStr::from_bytes(&row.get::<_, Vec<u8>>("path")?)),
+                    ExistingSong {
+                        txt: file_state("txt_mtime", "txt_size", "txt_hash")?,
+                        audio_file: row
+                            .get::<_, Option<Vec<u8>>>("audio_file")?
+                            .map(|bytes| PathBuf::from(OsStr::from_bytes(&bytes))),
+                        audio: file_state("audio_mtime", "audio_size", "audio_hash")?,
+                    },
+                ))
             })?
             .collect::<Result<_, _>>()?;
-        let mut new_songs = HashSet::new();
+        let existing_count = existing_songs.len();
+
+        // The stored paths are relative to the media root, so they all have to be updated when it changes.
+        // Databases from before the media root was recorded used a different scheme.
+        let media_root_changed =
+            db::update_setting(&tx, "media_root", media_root.as_os_str().as_bytes())?
+                && existing_count > 0;
+        if media_root_changed {
+            println!("The media root changed, rescanning all songs.");
+        }
+        // Likewise, the song ids are derived from the paths relative to the collection root.
+        let root_changed =
+            db::update_setting(&tx, "root", root.as_os_str().as_bytes())? && existing_count > 0;
+        if root_changed && !media_root_changed {
+            println!("The collection root changed, rescanning all songs.");
+        }
 
-        let mut insert_stmt = tx.prepare(
-            r#"INSERT INTO song (path, title, artist, language, year, duration, lyrics, player_count, cover_path, audio_path) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
-            ON CONFLICT (path) DO UPDATE SET title=?2, artist=?3, language=?4, year=?5, duration=?6, lyrics=?7, player_count = ?8, cover_path=?9, audio_path=?10"#)?;
-        walk_dir(
-            args.path,
-            args.strip_components,
-            &mut insert_stmt,
-            &mut new_songs,
+        let mut insert_stmt = tx.prepare(db::INSERT_SONG)?;
+        let mut touch_stmt = tx.prepare(
+            "UPDATE song SET txt_mtime=?2, txt_size=?3, audio_mtime=?4, audio_size=?5 WHERE path=?1",
         )?;
 
-        let added = new_songs.difference(&existing_songs).count();
-        let removed: Vec<_> = existing_songs.difference(&new_songs).collect();
+        let scanner = Scanner {
+            root: root.clone(),
+            media_root: media_root.clone(),
+            full: args.full || media_root_changed || root_changed,
+            existing_songs,
+            previews: previews.clone(),
+        };
+        let (sender, receiver) = sync_channel(pool.current_num_threads() * 4);
 
-        let removed_count = if removed.is_empty() {
-            0
-        } else {
+        let mut new_songs = HashSet::new();
+        let mut report = Report {
+            diff: args.dry_run.then(Diff::default),
+            ..Default::default()
+        };
+        let mut select_stmt = tx.prepare(&format!(
+            "SELECT {} FROM song WHERE path=?1",
+            COMPARED_FIELDS.join(", ")
+        ))?;
+        let mut walk_error = None;
+
+        // The workers parse and probe the songs, while this thread is the only one writing to the database.
+        thread::scope(|scope| -> anyhow::Result<()> {
+            scope.spawn(|| {
+                pool.scope(|pool_scope| scanner.walk_dir(pool_scope, scanner.root.clone(), sender))
+            });
+
+            for scanned in receiver {
+                match scanned {
+                    Scanned::Unchanged(path) => {
+                        new_songs.insert(path);
+                        report.summary.unchanged += 1;
+                    }
+                    Scanned::Touched { path, txt, audio } => {
+                        touch_stmt.execute((
+                            path.as_os_str().as_bytes(),
+                            txt.mtime,
+                            txt.size,
+                            audio.mtime,
+                            audio.size,
+                        ))?;
+                        new_songs.insert(path);
+                        report.summary.unchanged += 1;
+                    }
+                    Scanned::Parsed(song) => {
+                        if let Some(diff) = &mut report.diff {
+                            diff.update(&mut select_stmt, &song)?;
+                        }
+                        let result = db::insert_song(&mut insert_stmt, &song);
+
+                        match result.category(FailureCategory::InsertFailure) {
+                            Ok(1) => {
+                                for warning in &song.warnings {
+                                    report.add_warning(&song.path, warning);
+                                }
+                                if scanner.existing_songs.contains_key(&song.path) {
+                                    report.summary.updated += 1;
+                                } else {
+                                    report.summary.added += 1;
+                                    if let Some(diff) = &mut report.diff {
+                                        diff.add(&song.path);
+                                    }
+                                }
+                                new_songs.insert(song.path);
+                            }
+                            Ok(changes) => report.add_failure(
+                                &song.path,
+                                &Failure {
+                                    category: FailureCategory::InsertFailure,
+                                    error: anyhow::anyhow!("{changes} rows changed"),
+                                },
+                            ),
+                            Err(failure) => report.add_failure(&song.path, &failure),
+                        }
+                    }
+                    Scanned::Failed { path, failure } => report.add_failure(&path, &failure),
+                    Scanned::WalkFailed(error) => {
+                        walk_error.get_or_insert(error);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        if let Some(error) = walk_error {
+            return Err(error);
+        }
+
+        let existing_songs = scanner.existing_songs;
+        let removed: Vec<_> = existing_songs
+            .keys()
+            .filter(|path| !new_songs.contains(*path))
+            .collect();
+
+        if let Some(diff) = &mut report.diff {
+            for path in &removed {
+                diff.remove(path);
+            }
+        }
+
+        if !removed.is_empty() {
             println!("Trying to remove {} songs...", removed.len());
             let mut remove_stmt = tx.prepare("DELETE FROM song WHERE path=?1")?;
-            removed
+            report.summary.removed = removed
                 .into_iter()
                 .map(|path| remove_stmt.execute((path.as_os_str().as_bytes(),)))
                 .collect::<Result<Vec<_>, _>>()?
                 .into_iter()
-                .sum()
-        };
+                .sum();
+        }
+
+        let (group_count, duplicate_count) = duplicates::group_duplicates(&tx)?;
+
+        if let Some(previews) = &previews {
+            let removed_previews = preview::remove_stale(previews, &db::song_ids(&tx)?)?;
+            if removed_previews > 0 {
+                println!("Removed {removed_previews} outdated previews.");
+            }
+        }
+
+        if let Some(report_path) = &args.report {
+            report.write(report_path, args.report_format)?;
+        } else {
+            for entry in report.skipped.iter().chain(&report.warnings) {
+                eprintln!("{}: {}", entry.path, entry.message);
+            }
+        }
 
-        println!("{added} new songs, {removed_count} removed");
+        if let Some(diff) = &report.diff {
+            diff.print();
+        }
+
+        let summary = &report.summary;
+        println!(
+            "{} new songs, {} updated, {} unchanged, {} removed, {} failed, {} with warnings",
+            summary.added,
+            summary.updated,
+            summary.unchanged,
+            summary.removed,
+            summary.failed,
+            summary.warnings
+        );
         println!(
             "Database now contains {} songs.",
-            existing_songs.len() - removed_count + added
+            existing_count - summary.removed + summary.added
         );
+        println!("Found {duplicate_count} songs in {group_count} groups of duplicates.");
+    }
+
+    if args.dry_run {
+        tx.rollback()?;
+        println!("Dry run, no changes were written.");
+    } else {
+        tx.commit()?;
+    }
+
+    if args.watch {
+        // Everything that changes from now on has to be parsed again.
+        let scanner = Scanner {
+            root,
+            media_root,
+            full: true,
+            existing_songs: HashMap::new(),
+            previews,
+        };
+        watch::watch(&mut conn, &scanner, &pool)?;
     }
-    tx.commit()?;
 
     Ok(())
 }
@@ -0,0 +1,143 @@
+use ultrastar_txt::{Line, Note};
+
+/// Statistics about the notes of a song, to estimate how hard it is to sing.
+#[derive(Debug, Default)]
+pub struct VocalMetrics {
+    /// Pitches are in semitones, as written in the txt file (0 is C4).
+    pub lowest_pitch: Option<i64>,
+    pub highest_pitch: Option<i64>,
+    pub pitch_range: Option<i64>,
+    /// The share of golden notes in the sung beats, from 0 to 1.
+    pub golden_share: Option<f64>,
+    /// The share of freestyle notes in the sung beats, from 0 to 1.
+    pub freestyle_share: Option<f64>,
+    pub notes_per_second: Option<f64>,
+}
+
+impl VocalMetrics {
+    /// `singing_time` is the length of the song after the gap, in seconds.
+    pub fn new(lines: &[Line], singing_time: f64) -> Self {
+        let mut lowest_pitch = None;
+        let mut highest_pitch = None;
+        let mut note_count = 0;
+        let mut beats = 0;
+        let mut golden_beats = 0;
+        let mut freestyle_beats = 0;
+
+        for note in lines.iter().flat_map(|line| &line.notes) {
+            let (pitch, duration) = match note {
+                Note::Regular {
+                    pitch, duration, ..
+                } => (Some(*pitch), *duration),
+                Note::Golden {
+                    pitch, duration, ..
+                } => {
+                    golden_beats += duration;
+                    (Some(*pitch), *duration)
+                }
+                // Freestyle notes aren't rated, so their pitch doesn't matter.
+                Note::Freestyle { duration, .. } => {
+                    freestyle_beats += duration;
+                    (None, *duration)
+                }
+                Note::PlayerChange { .. } => continue,
+            };
+            note_count += 1;
+            beats += duration;
+            if let Some(pitch) = pitch.map(i64::from) {
+                lowest_pitch = Some(lowest_pitch.map_or(pitch, |lowest: i64| lowest.min(pitch)));
+                highest_pitch =
+                    Some(highest_pitch.map_or(pitch, |highest: i64| highest.max(pitch)));
+            }
+        }
+
+        let share = |part| (beats > 0).then(|| f64::from(part) / f64::from(beats));
+        Self {
+            lowest_pitch,
+            highest_pitch,
+            pitch_range: lowest_pitch
+                .zip(highest_pitch)
+                .map(|(lowest, highest)| highest - lowest),
+            golden_share: share(golden_beats),
+            freestyle_share: share(freestyle_beats),
+            notes_per_second: (note_count > 0 && singing_time > 0.0)
+                .then(|| note_count as f64 / singing_time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(notes: Vec<Note>) -> Line {
+        Line {
+            start: 0,
+            rel: None,
+            notes,
+        }
+    }
+
+    fn regular(pitch: i32, duration: i32) -> Note {
+        Note::Regular {
+            start: 0,
+            duration,
+            pitch,
+            text: "la".to_owned(),
+        }
+    }
+
+    #[test]
+    fn counts_pitches_and_shares() {
+        let lines = [
+            line(vec![
+                regular(-5, 4),
+                Note::Golden {
+                    start: 4,
+                    duration: 2,
+                    pitch: 7,
+                    text: "la".to_owned(),
+                },
+            ]),
+            line(vec![
+                Note::Freestyle {
+                    start: 8,
+                    duration: 2,
+                    pitch: 40,
+                    text: "la".to_owned(),
+                },
+                regular(2, 2),
+            ]),
+        ];
+        let metrics = VocalMetrics::new(&lines, 2.0);
+        assert_eq!(metrics.lowest_pitch, Some(-5));
+        // The pitch of freestyle notes is ignored.
+        assert_eq!(metrics.highest_pitch, Some(7));
+        assert_eq!(metrics.pitch_range, Some(12));
+        assert_eq!(metrics.golden_share, Some(0.2));
+        assert_eq!(metrics.freestyle_share, Some(0.2));
+        assert_eq!(metrics.notes_per_second, Some(2.0));
+    }
+
+    #[test]
+    fn ignores_player_changes() {
+        let lines = [line(vec![
+            Note::PlayerChange { player: 1 },
+            regular(0, 1),
+            Note::PlayerChange { player: 2 },
+            regular(3, 1),
+        ])];
+        let metrics = VocalMetrics::new(&lines, 1.0);
+        assert_eq!(metrics.pitch_range, Some(3));
+        assert_eq!(metrics.notes_per_second, Some(2.0));
+    }
+
+    #[test]
+    fn song_without_notes_has_no_metrics() {
+        let metrics = VocalMetrics::new(&[], 0.0);
+        assert_eq!(metrics.lowest_pitch, None);
+        assert_eq!(metrics.pitch_range, None);
+        assert_eq!(metrics.golden_share, None);
+        assert_eq!(metrics.notes_per_second, None);
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use ffmpeg_next::{
+    codec, decoder, encoder,
+    filter::{self, Graph},
+    format::{self, context::Output},
+    frame, media, ChannelLayout, Packet, Rational,
+};
+
+/// The length of the preview clips, in seconds.
+pub const PREVIEW_LENGTH: f64 = 15.0;
+/// Low enough to keep the clips small on party WiFi, high enough to recognize the song.
+const BIT_RATE: usize = 64_000;
+const FADE_LENGTH: f64 = 1.0;
+const EXTENSION: &str = "m4a";
+
+/// Returns where the preview of the song with the given id is stored.
+pub fn preview_path(dir: &Path, song_id: &str) -> PathBuf {
+    dir.join(format!("{song_id}.{EXTENSION}"))
+}
+
+/// Cuts a clip of [`PREVIEW_LENGTH`] seconds from `audio_path`, starting at `start` seconds, and
+/// writes it as AAC to `target`. The clip fades in and out, so it doesn't start and end abruptly.
+pub fn cut_preview(audio_path: &Path, target: &Path, start: f64) -> anyhow::Result<()> {
+    // Write to a temporary file first, so that the server never serves a partial clip.
+    let temp_target = target.with_extension(format!("tmp.{EXTENSION}"));
+    if let Err(err) = write_clip(audio_path, &temp_target, start) {
+        // Encoding might have failed after the file was created.
+        if let Err(remove_err) = fs::remove_file(&temp_target) {
+            if remove_err.kind() != ErrorKind::NotFound {
+                return Err(err.context(format!("Failed deleting {temp_target:?}: {remove_err}")));
+            }
+        }
+        return Err(err);
+    }
+    fs::rename(&temp_target, target)?;
+    Ok(())
+}
+
+fn write_clip(audio_path: &Path, target: &Path, start: f64) -> anyhow::Result<()> {
+    let mut input = format::input(&audio_path)?;
+    let stream = input
+        .streams()
+        .best(media::Type::Audio)
+        .context("No audio stream")?;
+    let stream_index = stream.index();
+    let input_time_base = stream.time_base();
+    let mut decoder = codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .audio()?;
+    decoder.set_parameters(stream.parameters())?;
+    if decoder.channel_layout().is_empty() {
+        decoder.set_channel_layout(ChannelLayout::default(decoder.channels() as _));
+    }
+
+    let mut output = format::output(&target)?;
+    let aac = encoder::find(codec::Id::AAC)
+        .context("No AAC encoder available")?
+        .audio()?;
+    let global_header = output
+        .format()
+        .flags()
+        .contains(format::flag::Flags::GLOBAL_HEADER);
+    let mut output_stream = output.add_stream(aac)?;
+    let mut encoder = codec::context::Context::from_parameters(output_stream.parameters())?
+        .encoder()
+        .audio()?;
+    if global_header {
+        encoder.set_flags(codec::flag::Flags::GLOBAL_HEADER);
+    }
+    encoder.set_rate(decoder.rate() as _);
+    encoder.set_channel_layout(ChannelLayout::STEREO);
+    encoder.set_channels(ChannelLayout::STEREO.channels());
+    encoder.set_format(
+        aac.formats()
+            .and_then(|mut formats| formats.next())
+            .context("The AAC encoder doesn't support any sample format")?,
+    );
+    encoder.set_bit_rate(BIT_RATE);
+    encoder.set_time_base((1, decoder.rate() as i32));
+    output_stream.set_time_base((1, decoder.rate() as i32));
+    let mut encoder = encoder.open_as(aac)?;
+    output_stream.set_parameters(&encoder);
+
+    let end = start + PREVIEW_LENGTH;
+    let mut graph = filter_graph(
+        &format!(
+            "atrim=start={start}:end={end},afade=t=in:st={start}:d={FADE_LENGTH},afade=t=out:st={}:d={FADE_LENGTH},asetpts=PTS-STARTPTS",
+            end - FADE_LENGTH
+        ),
+        &decoder,
+        &encoder,
+    )?;
+
+    output.write_header()?;
+    let output_time_base = output
+        .stream(0)
+        .context("The output stream disappeared")?
+        .time_base();
+    let mut clip = Clip {
+        graph: &mut graph,
+        encoder: &mut encoder,
+        output: &mut output,
+        time_bases: (decoder.time_base(), output_time_base),
+    };
+
+    // Only decode what's needed. Seeking lands on the closest keyframe before the start.
+    let seek_target = (start * f64::from(ffmpeg_next::ffi::AV_TIME_BASE)) as i64;
+    input.seek(seek_target, ..seek_target)?;
+    for (stream, mut packet) in input.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+        if packet
+            .pts()
+            .is_some_and(|pts| pts as f64 * f64::from(input_time_base) > end)
+        {
+            break;
+        }
+        packet.rescale_ts(input_time_base, decoder.time_base());
+        decoder.send_packet(&packet)?;
+        clip.decode_frames(&mut decoder)?;
+    }
+    decoder.send_eof()?;
+    clip.decode_frames(&mut decoder)?;
+    clip.graph
+        .get("in")
+        .context("The filter graph has no input")?
+        .source()
+        .flush()?;
+    clip.encode_filtered()?;
+    clip.encoder.send_eof()?;
+    clip.write_packets()?;
+    output.write_trailer()?;
+    Ok(())
+}
+
+/// The filter converts the decoded audio into the encoder's format and cuts out the clip.
+fn filter_graph(
+    spec: &str,
+    decoder: &decoder::Audio,
+    encoder: &encoder::Audio,
+) -> anyhow::Result<Graph> {
+    let mut graph = Graph::new();
+    let args = format!(
+        "time_base={}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+        decoder.time_base(),
+        decoder.rate(),
+        decoder.format().name(),
+        decoder.channel_layout().bits()
+    );
+    graph.add(
+        &filter::find("abuffer").context("No abuffer filter")?,
+        "in",
+        &args,
+    )?;
+    graph.add(
+        &filter::find("abuffersink").context("No abuffersink filter")?,
+        "out",
+        "",
+    )?;
+    {
+        let mut out = graph.get("out").context("The filter graph has no output")?;
+        out.set_sample_format(encoder.format());
+        out.set_channel_layout(encoder.channel_layout());
+        out.set_sample_rate(encoder.rate());
+    }
+    graph.output("in", 0)?.input("out", 0)?.parse(spec)?;
+    graph.validate()?;
+    // The AAC encoder needs frames of exactly the size it asks for.
+    if let Some(codec) = encoder.codec() {
+        if !codec
+            .capabilities()
+            .contains(codec::capabilities::Capabilities::VARIABLE_FRAME_SIZE)
+        {
+            graph
+                .get("out")
+                .context("The filter graph has no output")?
+                .sink()
+                .set_frame_size(encoder.frame_size());
+        }
+    }
+    Ok(graph)
+}
+
+/// The state of writing a clip, from decoded frames to muxed packets.
+struct Clip<'c> {
+    graph: &'c mut Graph,
+    encoder: &'c mut encoder::Audio,
+    output: &'c mut Output,
+    /// The time bases of the decoder and the output stream.
+    time_bases: (Rational, Rational),
+}
+
+impl Clip<'_> {
+    fn decode_frames(&mut self, decoder: &mut decoder::Audio) -> anyhow::Result<()> {
+        let mut decoded = frame::Audio::empty();
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            let timestamp = decoded.timestamp();
+            decoded.set_pts(timestamp);
+            self.graph
+                .get("in")
+                .context("The filter graph has no input")?
+                .source()
+                .add(&decoded)?;
+            self.encode_filtered()?;
+        }
+        Ok(())
+    }
+
+    fn encode_filtered(&mut self) -> anyhow::Result<()> {
+        let mut filtered = frame::Audio::empty();
+        while self
+            .graph
+            .get("out")
+            .context("The filter graph has no output")?
+            .sink()
+            .frame(&mut filtered)
+            .is_ok()
+        {
+            self.encoder.send_frame(&filtered)?;
+            self.write_packets()?;
+        }
+        Ok(())
+    }
+
+    fn write_packets(&mut self) -> anyhow::Result<()> {
+        let mut encoded = Packet::empty();
+        while self.encoder.receive_packet(&mut encoded).is_ok() {
+            encoded.set_stream(0);
+            encoded.rescale_ts(self.time_bases.0, self.time_bases.1);
+            encoded.write_interleaved(self.output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Deletes the previews of songs that aren't in the database anymore, along with temporary files
+/// of clips that were never finished.
+pub fn remove_stale(dir: &Path, song_ids: &HashSet<String>) -> anyhow::Result<usize> {
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_current = path.extension().is_some_and(|ext| ext == EXTENSION)
+            && path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| song_ids.contains(stem));
+        if !is_current && path.is_file() {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_previews_of_removed_songs() {
+        let dir = std::env::temp_dir().join(format!("preview-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["kept.m4a", "removed.m4a", "kept.tmp.m4a", "other.txt"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        fs::create_dir_all(dir.join("subdir")).unwrap();
+
+        let song_ids = HashSet::from(["kept".to_owned()]);
+        assert_eq!(remove_stale(&dir, &song_ids).unwrap(), 3);
+        let mut left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["kept.m4a", "subdir"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
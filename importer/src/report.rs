@@ -0,0 +1,143 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::diff::Diff;
+
+/// Why a song was skipped, or had problems during import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCategory {
+    /// The txt file couldn't be read or parsed.
+    ParseError,
+    /// The audio file doesn't exist.
+    MissingAudio,
+    /// The audio file isn't a local file.
+    RemoteAudio,
+    /// The audio file couldn't be opened or doesn't contain an audio stream.
+    NoAudioStream,
+    /// The cover isn't a local file. The song is still imported, but without a cover.
+    RemoteCover,
+    /// A media file isn't below the media root, so it can't be served. Songs with such an audio
+    /// file are skipped, other media files are left out.
+    OutsideMediaRoot,
+    /// The loudness of the audio couldn't be measured. The song is still imported, but without
+    /// loudness information.
+    LoudnessAnalysis,
+    /// Some of the languages aren't known. The song is still imported, with only the known
+    /// languages normalized.
+    UnknownLanguage,
+    /// The preview clip couldn't be cut. The song is still imported, but without a preview.
+    PreviewFailure,
+    /// Writing the song to the database failed.
+    InsertFailure,
+}
+
+/// An error that caused a song to be skipped (or a warning for a song that was imported anyways),
+/// along with its category for the report.
+#[derive(Debug)]
+pub struct Failure {
+    pub category: FailureCategory,
+    pub error: anyhow::Error,
+}
+
+pub trait Categorize<T> {
+    fn category(self, category: FailureCategory) -> Result<T, Failure>;
+}
+
+impl<T, E: Into<anyhow::Error>> Categorize<T> for Result<T, E> {
+    fn category(self, category: FailureCategory) -> Result<T, Failure> {
+        self.map_err(|error| Failure {
+            category,
+            error: error.into(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportEntry {
+    pub path: String,
+    pub category: FailureCategory,
+    pub message: String,
+}
+
+impl ReportEntry {
+    fn new(path: &Path, failure: &Failure) -> Self {
+        Self {
+            path: path.to_string_lossy().into_owned(),
+            category: failure.category,
+            message: format!("{:#}", failure.error),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub failed: usize,
+    pub warnings: usize,
+}
+
+/// Machine-readable list of everything that went wrong during an import, for the people
+/// maintaining the song collection.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub skipped: Vec<ReportEntry>,
+    /// Songs that were imported, but had problems.
+    pub warnings: Vec<ReportEntry>,
+    pub summary: Summary,
+    /// Only collected in dry-run mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<Diff>,
+}
+
+impl Report {
+    pub fn add_failure(&mut self, path: &Path, failure: &Failure) {
+        self.skipped.push(ReportEntry::new(path, failure));
+        self.summary.failed += 1;
+    }
+
+    pub fn add_warning(&mut self, path: &Path, warning: &Failure) {
+        self.warnings.push(ReportEntry::new(path, warning));
+        self.summary.warnings += 1;
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>, format: ReportFormat) -> anyhow::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        match format {
+            ReportFormat::Json => serde_json::to_writer_pretty(file, self)?,
+            ReportFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(file);
+                writer.write_record(["path", "category", "message"])?;
+                for entry in self.skipped.iter().chain(&self.warnings) {
+                    writer.serialize(entry)?;
+                }
+                // The summary is appended as rows without a path, with the count as the message.
+                for (name, count) in [
+                    ("added", self.summary.added),
+                    ("updated", self.summary.updated),
+                    ("unchanged", self.summary.unchanged),
+                    ("removed", self.summary.removed),
+                    ("failed", self.summary.failed),
+                    ("warnings", self.summary.warnings),
+                ] {
+                    writer.write_record(["", name, &count.to_string()])?;
+                }
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,699 @@
+use std::{
+    collections::HashMap,
+    fs::{metadata, read_dir},
+    os::unix::ffi::OsStrExt,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::SyncSender,
+    time::UNIX_EPOCH,
+};
+
+use rayon::Scope;
+use ultrastar_txt::{Header, Line, Note, Source};
+
+use crate::{
+    language, loudness,
+    metrics::VocalMetrics,
+    preview,
+    report::{Categorize, Failure, FailureCategory},
+    txt::{self, DetectedEncoding},
+};
+
+/// Modification time, size and content hash of a file, used to detect changes between imports.
+#[derive(Debug, Clone)]
+pub struct FileState {
+    pub mtime: i64,
+    pub size: i64,
+    pub hash: String,
+}
+
+impl FileState {
+    fn read(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let (mtime, size) = stat(&path)?;
+        Ok(Self {
+            mtime,
+            size,
+            hash: sha256::try_digest(path.as_ref())?,
+        })
+    }
+
+    /// Returns the current state of the file if its mtime or size differ from `self`, `None`
+    /// otherwise. The content hash is only computed in the former case.
+    fn refresh(&self, path: impl AsRef<Path>) -> anyhow::Result<Option<Self>> {
+        let (mtime, size) = stat(&path)?;
+        if mtime == self.mtime && size == self.size {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            mtime,
+            size,
+            hash: sha256::try_digest(path.as_ref())?,
+        }))
+    }
+}
+
+fn stat(path: impl AsRef<Path>) -> anyhow::Result<(i64, i64)> {
+    let metadata = metadata(path)?;
+    let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos() as i64;
+    Ok((mtime, metadata.len() as i64))
+}
+
+/// What we know about a song from the previous import.
+#[derive(Debug)]
+pub struct ExistingSong {
+    pub txt: Option<FileState>,
+    pub audio_file: Option<PathBuf>,
+    pub audio: Option<FileState>,
+}
+
+enum Freshness {
+    Changed,
+    Unchanged,
+    /// The files were touched, but their contents are the same.
+    Touched {
+        txt: FileState,
+        audio: FileState,
+    },
+}
+
+impl ExistingSong {
+    /// Checks whether the txt and audio files are the same as during the last import.
+    fn freshness(&self, full_path: &Path) -> anyhow::Result<Freshness> {
+        let (Some(txt), Some(audio_file), Some(audio)) = (&self.txt, &self.audio_file, &self.audio)
+        else {
+            return Ok(Freshness::Changed);
+        };
+        let txt_now = txt.refresh(full_path)?;
+        let audio_now = audio.refresh(audio_file)?;
+        if txt_now.as_ref().is_some_and(|now| now.hash != txt.hash)
+            || audio_now.as_ref().is_some_and(|now| now.hash != audio.hash)
+        {
+            return Ok(Freshness::Changed);
+        }
+
+        if txt_now.is_none() && audio_now.is_none() {
+            Ok(Freshness::Unchanged)
+        } else {
+            Ok(Freshness::Touched {
+                txt: txt_now.unwrap_or_else(|| txt.clone()),
+                audio: audio_now.unwrap_or_else(|| audio.clone()),
+            })
+        }
+    }
+}
+
+/// A song that was parsed and probed, ready to be written to the database.
+#[derive(Debug)]
+pub struct ParsedSong {
+    pub path: PathBuf,
+    /// Stable identifier of the song, see [`Scanner::song_id`].
+    pub song_id: String,
+    pub title: String,
+    pub artist: String,
+    /// The language as written in the txt file.
+    pub language: Option<String>,
+    /// The ISO 639 codes of the languages, see [`language::normalize`].
+    pub language_codes: Vec<&'static str>,
+    pub year: Option<i64>,
+    pub duration: f64,
+    pub lyrics: String,
+    pub player_count: u32,
+    /// Integrated loudness according to EBU R128, in LUFS.
+    pub loudness: Option<f64>,
+    /// True peak of the audio, in dBTP.
+    pub true_peak: Option<f64>,
+    /// The lyrics of the first and second voice, only for duets.
+    pub lyrics_p1: Option<String>,
+    pub lyrics_p2: Option<String>,
+    /// The singer labels of the voices from the `#P1`/`#P2` tags, only for duets.
+    pub singer_p1: Option<String>,
+    pub singer_p2: Option<String>,
+    pub genre: Option<String>,
+    pub edition: Option<String>,
+    pub creator: Option<String>,
+    /// Where the preview should start, in seconds.
+    pub preview_start: Option<f64>,
+    pub medley_start_beat: Option<i64>,
+    pub medley_end_beat: Option<i64>,
+    pub bpm: f64,
+    /// Delay until the first beat, in milliseconds.
+    pub gap: Option<f64>,
+    pub video_path: Option<Vec<u8>>,
+    pub background_path: Option<Vec<u8>>,
+    pub cover_path: Option<Vec<u8>>,
+    /// The cover URL if it isn't a local file.
+    pub cover_url: Option<String>,
+    pub audio_path: Vec<u8>,
+    pub metrics: VocalMetrics,
+    pub txt: FileState,
+    /// The encoding the txt file was decoded with.
+    pub encoding: DetectedEncoding,
+    pub audio_file: PathBuf,
+    pub audio: FileState,
+    /// Problems that didn't prevent the song from being imported.
+    pub warnings: Vec<Failure>,
+}
+
+/// The outcome of scanning a single txt file, sent from the worker pool to the database writer.
+#[derive(Debug)]
+pub enum Scanned {
+    /// The song didn't change since the last import.
+    Unchanged(PathBuf),
+    /// The song didn't change, but its files have a new mtime that should be recorded, so they
+    /// aren't hashed again next time.
+    Touched {
+        path: PathBuf,
+        txt: FileState,
+        audio: FileState,
+    },
+    Parsed(Box<ParsedSong>),
+    /// The song couldn't be imported and is skipped.
+    Failed {
+        path: PathBuf,
+        failure: Failure,
+    },
+    /// A directory couldn't be read. The import has to be aborted, because otherwise all songs
+    /// below it would be removed from the database.
+    WalkFailed(anyhow::Error),
+}
+
+pub struct Scanner {
+    /// The canonicalized root directory of the song collection.
+    pub root: PathBuf,
+    /// The canonicalized directory served by the web server, the stored media paths are relative
+    /// to it.
+    pub media_root: PathBuf,
+    pub full: bool,
+    pub existing_songs: HashMap<PathBuf, ExistingSong>,
+    /// Where to store the preview clips, if they should be generated.
+    pub previews: Option<PathBuf>,
+}
+
+impl Scanner {
+    /// Recursively walks `path`, spawning a task on the pool for every subdirectory and txt file.
+    pub fn walk_dir<'s>(&'s self, scope: &Scope<'s>, path: PathBuf, sender: SyncSender<Scanned>) {
+        if let Err(err) = self.try_walk_dir(scope, &path, &sender) {
+            sender
+                .send(Scanned::WalkFailed(err.context(format!("{path:?}"))))
+                .ok();
+        }
+    }
+
+    fn try_walk_dir<'s>(
+        &'s self,
+        scope: &Scope<'s>,
+        path: &Path,
+        sender: &SyncSender<Scanned>,
+    ) -> anyhow::Result<()> {
+        for subdir in read_dir(path)? {
+            let subdir = subdir?;
+
+            // Retrieve metadata based on the actual file or symlink.
+            let file_type = subdir.file_type()?;
+
+            if file_type.is_dir() || (file_type.is_symlink() && metadata(subdir.path())?.is_dir()) {
+                // Recursive call if it's a directory or a symlink pointing to a directory
+                let sender = sender.clone();
+                scope.spawn(move |scope| self.walk_dir(scope, subdir.path(), sender));
+            } else if file_type.is_file() {
+                // Handle files, specifically .txt files.
+                let file_path = subdir.path();
+                if let Some(ext) = file_path.extension() {
+                    if ext == "txt" {
+                        self.spawn_parse(scope, file_path, sender.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses a single txt file on the pool.
+    pub fn spawn_parse<'s>(
+        &'s self,
+        scope: &Scope<'s>,
+        path: PathBuf,
+        sender: SyncSender<Scanned>,
+    ) {
+        scope.spawn(move |_| {
+            let scanned = self
+                .parse_txt(&path)
+                .unwrap_or_else(|failure| Scanned::Failed { path, failure });
+            // Only fails if the writer is gone, in which case the import was aborted anyways.
+            sender.send(scanned).ok();
+        });
+    }
+
+    fn parse_txt(&self, path: impl AsRef<Path>) -> Result<Scanned, Failure> {
+        let full_path = path
+            .as_ref()
+            .canonicalize()
+            .category(FailureCategory::ParseError)?;
+
+        if !self.full {
+            if let Some(existing) = self.existing_songs.get(&full_path) {
+                // If checking fails, we do a full parse to find out what's wrong.
+                // Songs imported before previews were enabled need to get one.
+                let missing_preview = self.previews.as_ref().is_some_and(|dir| {
+                    !preview::preview_path(dir, &self.song_id(&full_path)).exists()
+                });
+                let freshness = if missing_preview {
+                    Freshness::Changed
+                } else {
+                    existing.freshness(&full_path).unwrap_or(Freshness::Changed)
+                };
+                match freshness {
+                    Freshness::Changed => {}
+                    Freshness::Unchanged => return Ok(Scanned::Unchanged(full_path)),
+                    Freshness::Touched { txt, audio } => {
+                        return Ok(Scanned::Touched {
+                            path: full_path,
+                            txt,
+                            audio,
+                        })
+                    }
+                }
+            }
+        }
+
+        let (song, encoding) =
+            txt::load_song(path.as_ref()).category(FailureCategory::ParseError)?;
+
+        let audio_path = match &song.header.audio_path {
+            Source::Local(audio_path) => audio_path,
+            Source::Remote(url) => {
+                return Err(anyhow::anyhow!(
+                    "{:?} does not have a local audio track, but {url}.",
+                    path.as_ref()
+                ))
+                .category(FailureCategory::RemoteAudio);
+            }
+        };
+
+        let full_audio_path = audio_path
+            .canonicalize()
+            .category(FailureCategory::MissingAudio)?;
+        let txt_state = FileState::read(&full_path).category(FailureCategory::ParseError)?;
+        let audio_state =
+            FileState::read(&full_audio_path).category(FailureCategory::MissingAudio)?;
+
+        let mut context =
+            ffmpeg_next::format::input(&audio_path).category(FailureCategory::NoAudioStream)?;
+        let Some(stream) = context.streams().best(ffmpeg_next::media::Type::Audio) else {
+            return Err(anyhow::anyhow!(
+                "{:?} does not contain an audio track.",
+                path.as_ref()
+            ))
+            .category(FailureCategory::NoAudioStream);
+        };
+        let duration = stream.duration() as f64 * f64::from(stream.time_base());
+        let stream_index = stream.index();
+        let loudness = loudness::analyze(&mut context, stream_index);
+
+        let creator = trimmed(unknown_tag(&song.header, "CREATOR").map(str::to_owned));
+        let preview_start = parse_tag(&song.header, "PREVIEWSTART");
+        let medley_start_beat = parse_tag(&song.header, "MEDLEYSTARTBEAT");
+        let medley_end_beat = parse_tag(&song.header, "MEDLEYENDBEAT");
+        // Older files use `#DUETSINGERP1` instead of `#P1`.
+        let singer_p1 = trimmed(
+            unknown_tag(&song.header, "P1")
+                .or_else(|| unknown_tag(&song.header, "DUETSINGERP1"))
+                .map(str::to_owned),
+        );
+        let singer_p2 = trimmed(
+            unknown_tag(&song.header, "P2")
+                .or_else(|| unknown_tag(&song.header, "DUETSINGERP2"))
+                .map(str::to_owned),
+        );
+
+        let audio_path = self
+            .media_path(audio_path)
+            .category(FailureCategory::OutsideMediaRoot)?;
+
+        let mut warnings = Vec::new();
+        let loudness = loudness.unwrap_or_else(|error| {
+            warnings.push(Failure {
+                category: FailureCategory::LoudnessAnalysis,
+                error: error.context(format!(
+                    "Measuring the loudness of {full_audio_path:?} failed"
+                )),
+            });
+            None
+        });
+        let language = trimmed(song.header.language);
+        let language_codes = match &language {
+            Some(language) => {
+                let (codes, unknown) = language::normalize(language);
+                if !unknown.is_empty() {
+                    warnings.push(Failure {
+                        category: FailureCategory::UnknownLanguage,
+                        error: anyhow::anyhow!("Unknown languages: {}", unknown.join(", ")),
+                    });
+                }
+                codes
+            }
+            None => Vec::new(),
+        };
+        let mut optional_media_path = |path: &Path| match self.media_path(path) {
+            Ok(media_path) => Some(media_path),
+            Err(error) => {
+                warnings.push(Failure {
+                    category: FailureCategory::OutsideMediaRoot,
+                    error,
+                });
+                None
+            }
+        };
+        let video_path = match song.header.video_path {
+            Some(Source::Local(video_path)) => optional_media_path(&video_path),
+            _ => None,
+        };
+        let background_path = match song.header.background_path {
+            Some(Source::Local(background_path)) => optional_media_path(&background_path),
+            _ => None,
+        };
+        let (cover_path, cover_url) = match song.header.cover_path {
+            Some(Source::Local(cover_path)) => (optional_media_path(&cover_path), None),
+            Some(Source::Remote(url)) => {
+                // We only serve local files, so the song is imported without a cover.
+                warnings.push(Failure {
+                    category: FailureCategory::RemoteCover,
+                    error: anyhow::anyhow!("Song {} has remote cover {url}", song.header.title),
+                });
+                (None, Some(url.to_string()))
+            }
+            None => (None, None),
+        };
+
+        let player_count = (song.lines.iter().any(|line| {
+            line.notes.iter().any(|note| match note {
+                ultrastar_txt::Note::PlayerChange { player } => *player == 2,
+                _ => false,
+            })
+        }) as u32)
+            + 1;
+
+        let (lyrics_p1, lyrics_p2, singer_p1, singer_p2) = if player_count > 1 {
+            let [lyrics_p1, lyrics_p2] = voice_lyrics(&song.lines);
+            (Some(lyrics_p1), Some(lyrics_p2), singer_p1, singer_p2)
+        } else {
+            (None, None, None, None)
+        };
+
+        let first_note_beat = song
+            .lines
+            .iter()
+            .flat_map(|line| &line.notes)
+            .find_map(|note| match note {
+                Note::Regular { start, .. }
+                | Note::Golden { start, .. }
+                | Note::Freestyle { start, .. } => Some(*start),
+                Note::PlayerChange { .. } => None,
+            });
+        let song_id = self.song_id(&full_path);
+        if let Some(previews) = &self.previews {
+            // Songs usually start with an instrumental intro, so start at the vocals if the song
+            // doesn't specify where the preview starts. Beats are quarter notes.
+            let gap = f64::from(song.header.gap.unwrap_or_default()) / 1000.0;
+            let start = preview_start
+                .or_else(|| {
+                    first_note_beat.map(|beat| {
+                        gap + f64::from(beat) * 60.0 / (f64::from(song.header.bpm) * 4.0)
+                    })
+                })
+                .unwrap_or_default()
+                .min(duration - preview::PREVIEW_LENGTH)
+                .max(0.0);
+            if let Err(error) = preview::cut_preview(
+                &full_audio_path,
+                &preview::preview_path(previews, &song_id),
+                start,
+            ) {
+                warnings.push(Failure {
+                    category: FailureCategory::PreviewFailure,
+                    error: error
+                        .context(format!("Cutting a preview of {full_audio_path:?} failed")),
+                });
+            }
+        }
+
+        let singing_time = duration - song.header.gap.map(f64::from).unwrap_or_default() / 1000.0;
+        let metrics = VocalMetrics::new(&song.lines, singing_time);
+
+        let lyrics = song
+            .lines
+            .into_iter()
+            .map(|line| {
+                line.notes
+                    .into_iter()
+                    .filter_map(|note| match note {
+                        ultrastar_txt::Note::Regular { text, .. } => Some(text),
+                        ultrastar_txt::Note::Golden { text, .. } => Some(text),
+                        ultrastar_txt::Note::Freestyle { text, .. } => Some(text),
+                        ultrastar_txt::Note::PlayerChange { .. } => None,
+                    })
+                    .collect::<String>()
+                    .trim()
+                    .to_owned()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Ok(Scanned::Parsed(Box::new(ParsedSong {
+            song_id,
+            path: full_path,
+            title: song.header.title.trim().to_owned(),
+            artist: song.header.artist.trim().to_owned(),
+            language,
+            language_codes,
+            year: song.header.year.map(i64::from),
+            duration,
+            lyrics,
+            player_count,
+            loudness: loudness.map(|loudness| loudness.integrated),
+            true_peak: loudness.map(|loudness| loudness.true_peak),
+            lyrics_p1,
+            lyrics_p2,
+            singer_p1,
+            singer_p2,
+            genre: trimmed(song.header.genre),
+            edition: trimmed(song.header.edition),
+            creator,
+            preview_start,
+            medley_start_beat,
+            medley_end_beat,
+            bpm: song.header.bpm.into(),
+            gap: song.header.gap.map(f64::from),
+            video_path,
+            background_path,
+            cover_path,
+            cover_url,
+            audio_path,
+            metrics,
+            txt: txt_state,
+            encoding,
+            audio_file: full_audio_path,
+            audio: audio_state,
+            warnings,
+        })))
+    }
+
+    /// Derives the song id from the txt path relative to the collection root, so it stays the
+    /// same when a song is removed and re-added, or when the whole collection is moved. The
+    /// importer rescans all songs when the root changes, so that no old ids are left behind.
+    fn song_id(&self, full_path: &Path) -> String {
+        let relative_path = full_path.strip_prefix(&self.root).unwrap_or(full_path);
+        let mut id = sha256::digest(relative_path.as_os_str().as_bytes());
+        id.truncate(16);
+        id
+    }
+
+    /// Converts a local path into the form stored in the database, relative to the media root
+    /// served by the web server.
+    ///
+    /// This works on the path as written instead of canonicalizing it, so symlinks below the media
+    /// root are kept and media files that don't exist still get a path.
+    fn media_path(&self, path: &Path) -> anyhow::Result<Vec<u8>> {
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    normalized.pop();
+                }
+                component => normalized.push(component),
+            }
+        }
+        let relative_path = normalized.strip_prefix(&self.media_root).map_err(|_| {
+            anyhow::anyhow!(
+                "{path:?} is not inside the media root {:?}",
+                self.media_root
+            )
+        })?;
+        Ok(relative_path.as_os_str().as_bytes().to_owned())
+    }
+}
+
+fn trimmed(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+/// Splits the lyrics of a duet into the two voices. Lines sung by both players end up in both.
+fn voice_lyrics(lines: &[Line]) -> [String; 2] {
+    let mut voices: [Vec<String>; 2] = Default::default();
+    let mut player = 1;
+    for line in lines {
+        let mut text = String::new();
+        for note in &line.notes {
+            match note {
+                Note::Regular { text: syllable, .. }
+                | Note::Golden { text: syllable, .. }
+                | Note::Freestyle { text: syllable, .. } => text.push_str(syllable),
+                Note::PlayerChange { player: next } => {
+                    push_voice_line(&mut voices, player, &text);
+                    text.clear();
+                    player = *next;
+                }
+            }
+        }
+        push_voice_line(&mut voices, player, &text);
+    }
+    voices.map(|lines| lines.join("\n"))
+}
+
+/// Player 3 means that both players sing.
+fn push_voice_line(voices: &mut [Vec<String>; 2], player: i32, text: &str) {
+    let text = text.trim();
+    if text.is_empty() {
+        return;
+    }
+    for (idx, voice) in voices.iter_mut().enumerate() {
+        if player == idx as i32 + 1 || player == 3 {
+            voice.push(text.to_owned());
+        }
+    }
+}
+
+/// Header tags not known to the parser, like `#CREATOR` or `#PREVIEWSTART`.
+fn unknown_tag<'h>(header: &'h Header, name: &str) -> Option<&'h str> {
+    header
+        .unknown
+        .iter()
+        .flatten()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Parses a numeric header tag. Some files use a comma as the decimal separator.
+fn parse_tag<T: FromStr>(header: &Header, name: &str) -> Option<T> {
+    unknown_tag(header, name)?
+        .trim()
+        .replace(',', ".")
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scanner(media_root: &str) -> Scanner {
+        Scanner {
+            root: Path::new(media_root).join("songs"),
+            media_root: PathBuf::from(media_root),
+            full: false,
+            existing_songs: HashMap::new(),
+            previews: None,
+        }
+    }
+
+    #[test]
+    fn media_path_is_relative_to_media_root() {
+        let scanner = scanner("/srv/media");
+        let path = scanner
+            .media_path(Path::new("/srv/media/songs/ABBA/Waterloo.mp3"))
+            .unwrap();
+        assert_eq!(path, b"songs/ABBA/Waterloo.mp3");
+    }
+
+    #[test]
+    fn media_path_resolves_dots_without_canonicalizing() {
+        let scanner = scanner("/srv/media");
+        let path = scanner
+            .media_path(Path::new("/srv/media/songs/ABBA/./../Queen/missing.mp3"))
+            .unwrap();
+        assert_eq!(path, b"songs/Queen/missing.mp3");
+    }
+
+    #[test]
+    fn media_path_outside_media_root_fails() {
+        let scanner = scanner("/srv/media");
+        assert!(scanner
+            .media_path(Path::new("/srv/other/song.mp3"))
+            .is_err());
+        assert!(scanner
+            .media_path(Path::new("/srv/media/../other/song.mp3"))
+            .is_err());
+        assert!(scanner
+            .media_path(Path::new("/srv/media-old/song.mp3"))
+            .is_err());
+    }
+
+    fn sung(text: &str) -> Note {
+        Note::Regular {
+            start: 0,
+            duration: 1,
+            pitch: 0,
+            text: text.to_owned(),
+        }
+    }
+
+    fn line(notes: Vec<Note>) -> Line {
+        Line {
+            start: 0,
+            rel: None,
+            notes,
+        }
+    }
+
+    #[test]
+    fn voice_lyrics_splits_duets() {
+        let lines = [
+            line(vec![
+                Note::PlayerChange { player: 1 },
+                sung("Hel"),
+                sung("lo "),
+            ]),
+            line(vec![sung("there")]),
+            line(vec![Note::PlayerChange { player: 2 }, sung("Hi")]),
+            line(vec![
+                Note::PlayerChange { player: 3 },
+                sung("To"),
+                sung("gether"),
+            ]),
+        ];
+        let [p1, p2] = voice_lyrics(&lines);
+        assert_eq!(p1, "Hello\nthere\nTogether");
+        assert_eq!(p2, "Hi\nTogether");
+    }
+
+    #[test]
+    fn voice_lyrics_switches_players_within_a_line() {
+        let lines = [line(vec![
+            Note::PlayerChange { player: 1 },
+            sung("One"),
+            Note::PlayerChange { player: 2 },
+            sung("Two"),
+        ])];
+        assert_eq!(voice_lyrics(&lines), ["One".to_owned(), "Two".to_owned()]);
+    }
+
+    #[test]
+    fn voice_lyrics_of_solo_songs_are_the_first_voice() {
+        let lines = [line(vec![sung("Solo")]), line(vec![sung("  ")])];
+        assert_eq!(voice_lyrics(&lines), ["Solo".to_owned(), String::new()]);
+    }
+}
@@ -0,0 +1,201 @@
+use std::{fs, path::Path};
+
+use anyhow::Context as _;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1250, WINDOWS_1252};
+use serde::Serialize;
+use ultrastar_txt::{Source, TXTSong};
+
+/// How the encoding of a txt file was determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncodingSource {
+    /// The file starts with a byte order mark.
+    Bom,
+    /// The file declares its encoding with an `#ENCODING` tag.
+    Tag,
+    /// The file is valid UTF-8.
+    Utf8,
+    /// The encoding was guessed from the byte frequencies.
+    Guessed,
+}
+
+impl EncodingSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Bom => "bom",
+            Self::Tag => "tag",
+            Self::Utf8 => "utf8",
+            Self::Guessed => "guessed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DetectedEncoding {
+    pub encoding: &'static Encoding,
+    pub source: EncodingSource,
+}
+
+/// Reads a txt file in whatever encoding it was written in and parses it.
+///
+/// The parser of `ultrastar_txt` guesses the encoding on its own, but gets it wrong for many of
+/// the short CP1252 files from older collections. So the file is decoded here, and only the UTF-8
+/// text is handed to the parser.
+pub fn load_song(path: &Path) -> anyhow::Result<(TXTSong, DetectedEncoding)> {
+    let bytes = fs::read(path)?;
+    let (text, detected) = decode(&bytes);
+    let mut song = TXTSong {
+        header: ultrastar_txt::parse_txt_header_str(&text)
+            .map_err(|err| anyhow::anyhow!("{err:?}"))?,
+        lines: ultrastar_txt::parse_txt_lines_str(&text)
+            .map_err(|err| anyhow::anyhow!("{err:?}"))?,
+    };
+
+    // Media paths are relative to the txt file.
+    let dir = path
+        .parent()
+        .context("The txt file has no parent directory")?;
+    let header = &mut song.header;
+    resolve(&mut header.audio_path, dir);
+    for source in [
+        &mut header.cover_path,
+        &mut header.background_path,
+        &mut header.video_path,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        resolve(source, dir);
+    }
+    Ok((song, detected))
+}
+
+fn resolve(source: &mut Source, dir: &Path) {
+    if let Source::Local(path) = source {
+        *path = dir.join(&*path);
+    }
+}
+
+/// Determines the encoding of a txt file and converts it to UTF-8, dropping the byte order mark.
+///
+/// An explicit byte order mark wins, then the `#ENCODING` tag, unless the tag claims UTF-8 for a
+/// file that isn't. Files that are valid UTF-8 are taken as such, everything else is guessed.
+pub fn decode(bytes: &[u8]) -> (String, DetectedEncoding) {
+    let (detected, bom_length) = if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let source = EncodingSource::Bom;
+        (DetectedEncoding { encoding, source }, bom_length)
+    } else if let Some(encoding) = tagged_encoding(bytes)
+        .filter(|&encoding| encoding != UTF_8 || std::str::from_utf8(bytes).is_ok())
+    {
+        let source = EncodingSource::Tag;
+        (DetectedEncoding { encoding, source }, 0)
+    } else if std::str::from_utf8(bytes).is_ok() {
+        let source = EncodingSource::Utf8;
+        let encoding = UTF_8;
+        (DetectedEncoding { encoding, source }, 0)
+    } else {
+        let mut detector = EncodingDetector::new();
+        detector.feed(bytes, true);
+        let encoding = detector.guess(None, false);
+        let source = EncodingSource::Guessed;
+        (DetectedEncoding { encoding, source }, 0)
+    };
+    let (text, _) = detected
+        .encoding
+        .decode_without_bom_handling(&bytes[bom_length..]);
+    (text.into_owned(), detected)
+}
+
+/// Looks for an `#ENCODING` tag in the header. The tag itself is ASCII in all encodings we care
+/// about, so this works on the raw bytes.
+fn tagged_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+    let header = bytes
+        .split(|&byte| byte == b'\n')
+        .map(|line| line.trim_ascii())
+        .take_while(|line| line.is_empty() || line.starts_with(b"#"));
+    for line in header {
+        let Some(separator) = line.iter().position(|&byte| byte == b':') else {
+            continue;
+        };
+        let (tag, value) = (&line[..separator], &line[separator + 1..]);
+        if !tag.eq_ignore_ascii_case(b"#ENCODING") {
+            continue;
+        }
+        // UltraStar Deluxe writes its own names instead of the standard labels.
+        let value = value.trim_ascii();
+        return match value.to_ascii_uppercase().as_slice() {
+            b"UTF8" => Some(UTF_8),
+            b"CP1250" => Some(WINDOWS_1250),
+            b"CP1252" => Some(WINDOWS_1252),
+            // `AUTO` and `LOCALE` leave the decision to the reader.
+            // If the tag could be read as ASCII, the file can't be UTF-16.
+            _ => Encoding::for_label(value).filter(|encoding| encoding.is_ascii_compatible()),
+        };
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::UTF_16LE;
+
+    use super::*;
+
+    #[test]
+    fn strips_byte_order_marks() {
+        let (text, detected) = decode(b"\xEF\xBB\xBF#TITLE:Caf\xC3\xA9");
+        assert_eq!(text, "#TITLE:Café");
+        assert_eq!(detected.encoding, UTF_8);
+        assert_eq!(detected.source, EncodingSource::Bom);
+
+        let (text, detected) = decode(b"\xFF\xFE#\0T\0");
+        assert_eq!(text, "#T");
+        assert_eq!(detected.encoding, UTF_16LE);
+    }
+
+    #[test]
+    fn follows_encoding_tag() {
+        // 0xB9 is "ą" in CP1250, but "¹" in CP1252.
+        let (text, detected) = decode(b"#TITLE:Pi\xB9tek\n#encoding: cp1250\n: 0 1 0 Pi\xB9\n");
+        assert!(text.starts_with("#TITLE:Piątek\n"));
+        assert_eq!(detected.encoding, WINDOWS_1250);
+        assert_eq!(detected.source, EncodingSource::Tag);
+
+        let (_, detected) = decode(b"#ENCODING:ISO-8859-2\n#TITLE:Pi\xB1tek\n");
+        assert_eq!(detected.encoding.name(), "ISO-8859-2");
+        assert_eq!(detected.source, EncodingSource::Tag);
+    }
+
+    #[test]
+    fn ignores_tags_after_the_header() {
+        let (_, detected) = decode(b"#TITLE:A\n: 0 1 0 a\n#ENCODING:CP1250\n");
+        assert_eq!(detected.source, EncodingSource::Utf8);
+    }
+
+    #[test]
+    fn ignores_wrong_utf8_tag() {
+        let (text, detected) = decode(b"#ENCODING:UTF8\n#ARTIST:Mot\xF6rhead\n");
+        assert_eq!(text, "#ENCODING:UTF8\n#ARTIST:Motörhead\n");
+        assert_eq!(detected.source, EncodingSource::Guessed);
+    }
+
+    #[test]
+    fn takes_valid_utf8_as_is() {
+        let (text, detected) = decode("#ARTIST:Beyoncé\n".as_bytes());
+        assert_eq!(text, "#ARTIST:Beyoncé\n");
+        assert_eq!(detected.encoding, UTF_8);
+        assert_eq!(detected.source, EncodingSource::Utf8);
+    }
+
+    #[test]
+    fn guesses_legacy_encodings() {
+        let (text, detected) = decode(
+            b"#ARTIST:Mot\xF6rhead\n#TITLE:Ace of Spades\n: 0 4 5 Gr\xFC\xDFe \xE0 la caf\xE9\n",
+        );
+        assert!(text.starts_with("#ARTIST:Motörhead\n"));
+        assert!(text.ends_with(": 0 4 5 Grüße à la café\n"));
+        assert_eq!(detected.encoding, WINDOWS_1252);
+        assert_eq!(detected.source, EncodingSource::Guessed);
+    }
+}
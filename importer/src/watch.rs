@@ -0,0 +1,208 @@
+use std::{
+    collections::HashSet,
+    fs::read_dir,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, sync_channel, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use notify::{event::ModifyKind, EventKind, RecursiveMode, Watcher};
+use rayon::ThreadPool;
+use rusqlite::Connection;
+
+use crate::{
+    db, duplicates, preview,
+    report::{Categorize, FailureCategory},
+    scan::{Scanned, Scanner},
+};
+
+/// How long the collection has to be quiet before a batch of changes is imported. Copying a song
+/// folder creates lots of events in quick succession, and the txt file might arrive before the
+/// audio file.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Watches the song collection and updates the database whenever txt files are added, changed or
+/// removed. Runs until the watcher fails.
+pub fn watch(conn: &mut Connection, scanner: &Scanner, pool: &ThreadPool) -> anyhow::Result<()> {
+    let (event_sender, events) = channel();
+    let mut watcher = notify::recommended_watcher(event_sender)?;
+    watcher.watch(&scanner.root, RecursiveMode::Recursive)?;
+    println!("Watching {:?} for changes...", scanner.root);
+
+    loop {
+        let mut changed = HashSet::new();
+        // Block until something happens, then collect everything until things calm down.
+        let mut timeout = None;
+        loop {
+            let event = match timeout {
+                None => events.recv()?,
+                Some(timeout) => match events.recv_timeout(timeout) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        anyhow::bail!("The file watcher stopped")
+                    }
+                },
+            };
+            let event = event?;
+            if event.kind.is_access() {
+                continue;
+            }
+            // Directories are only interesting when they're new, not when their contents or
+            // attributes change.
+            let new_dir = event.kind.is_create()
+                || matches!(event.kind, EventKind::Modify(ModifyKind::Name(_)));
+            changed.extend(
+                event
+                    .paths
+                    .into_iter()
+                    .filter(|path| new_dir || !path.is_dir()),
+            );
+            timeout = Some(DEBOUNCE);
+        }
+
+        update(conn, scanner, pool, changed)?;
+    }
+}
+
+/// Re-imports everything affected by the changed paths in a single transaction.
+fn update(
+    conn: &mut Connection,
+    scanner: &Scanner,
+    pool: &ThreadPool,
+    changed: HashSet<PathBuf>,
+) -> anyhow::Result<()> {
+    // Changes to other files (like the audio file arriving after the txt file) rescan the song
+    // directory they're in. A txt file inside a directory that's rescanned anyways is skipped.
+    let mut dirs = HashSet::new();
+    let mut txt_files = HashSet::new();
+    let mut removed = Vec::new();
+    for path in changed {
+        let is_txt = path.extension().is_some_and(|ext| ext == "txt");
+        if path.is_dir() {
+            dirs.insert(path);
+            continue;
+        }
+        if !path.exists() {
+            removed.push(path.clone());
+        } else if is_txt {
+            txt_files.insert(path);
+            continue;
+        }
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !is_txt && is_song_dir(parent))
+        {
+            dirs.insert(parent.to_owned());
+        }
+    }
+    dirs.retain(|dir| dir.starts_with(&scanner.root));
+    let is_rescanned = |path: &Path| {
+        path.ancestors()
+            .skip(1)
+            .any(|ancestor| dirs.contains(ancestor))
+    };
+    let dirs: Vec<_> = dirs
+        .iter()
+        .filter(|dir| !is_rescanned(dir))
+        .cloned()
+        .collect();
+    txt_files.retain(|path| !is_rescanned(path));
+
+    let tx = conn.transaction()?;
+    let mut removed_count = 0;
+    {
+        // The path itself for txt files, or everything below it for directories.
+        let mut remove_stmt =
+            tx.prepare("DELETE FROM song WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2")?;
+        let mut remove = |path: &Path| -> rusqlite::Result<usize> {
+            let mut prefix = path.as_os_str().as_bytes().to_owned();
+            prefix.push(b'/');
+            remove_stmt.execute((path.as_os_str().as_bytes(), prefix))
+        };
+        for path in &removed {
+            removed_count += remove(path)?;
+        }
+
+        let mut insert_stmt = tx.prepare(db::INSERT_SONG)?;
+        let (sender, receiver) = sync_channel(pool.current_num_threads() * 4);
+        let mut imported = 0;
+        let mut failed = 0;
+        let mut walk_error = None;
+        thread::scope(|scope| -> anyhow::Result<()> {
+            scope.spawn(|| {
+                pool.scope(|pool_scope| {
+                    for dir in dirs {
+                        scanner.walk_dir(pool_scope, dir, sender.clone());
+                    }
+                    for path in txt_files {
+                        scanner.spawn_parse(pool_scope, path, sender.clone());
+                    }
+                    drop(sender);
+                })
+            });
+
+            for scanned in receiver {
+                match scanned {
+                    Scanned::Parsed(song) => {
+                        match db::insert_song(&mut insert_stmt, &song)
+                            .category(FailureCategory::InsertFailure)
+                        {
+                            Ok(_) => {
+                                println!("Imported {:?}", song.path);
+                                for warning in &song.warnings {
+                                    eprintln!("{:?}: {:#}", song.path, warning.error);
+                                }
+                                imported += 1;
+                            }
+                            Err(failure) => {
+                                eprintln!("{:?}: {:#}", song.path, failure.error);
+                                failed += 1;
+                            }
+                        }
+                    }
+                    Scanned::Failed { path, failure } => {
+                        // Like a full import, songs that can't be imported anymore are removed.
+                        eprintln!("{path:?}: {:#}", failure.error);
+                        removed_count += remove(&path.canonicalize().unwrap_or(path))?;
+                        failed += 1;
+                    }
+                    Scanned::WalkFailed(error) => {
+                        walk_error.get_or_insert(error);
+                    }
+                    // The scanner always parses in watch mode.
+                    Scanned::Unchanged(_) | Scanned::Touched { .. } => {}
+                }
+            }
+            Ok(())
+        })?;
+
+        if let Some(error) = walk_error {
+            // Keep watching, the next change in that directory will try again.
+            eprintln!("{error:#}");
+        }
+        if imported + failed + removed_count > 0 {
+            println!("{imported} songs imported, {removed_count} removed, {failed} failed");
+            duplicates::group_duplicates(&tx)?;
+        }
+        if let Some(previews) = scanner.previews.as_ref().filter(|_| removed_count > 0) {
+            let removed_previews = preview::remove_stale(previews, &db::song_ids(&tx)?)?;
+            if removed_previews > 0 {
+                println!("Removed {removed_previews} outdated previews.");
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Whether the directory directly contains a txt file.
+fn is_song_dir(dir: &Path) -> bool {
+    read_dir(dir).is_ok_and(|entries| {
+        entries
+            .flatten()
+            .any(|entry| entry.path().extension().is_some_and(|ext| ext == "txt"))
+    })
+}
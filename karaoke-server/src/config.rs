@@ -10,8 +10,14 @@ use tokio::fs::read;
 pub struct Paths {
     /// The path to the sqlite database with the song information.
     pub database: PathBuf,
-    /// Path to the directory structure for the covers.
+    /// Path to the directory structure for the covers and audio files. Has to be the media root the
+    /// importer was run with.
     pub media: PathBuf,
+    /// Directory for the generated cover thumbnails. Defaults to `cover_cache` next to the
+    /// database.
+    pub cover_cache: Option<PathBuf>,
+    /// The directory with the preview clips cut by the importer (its `--previews` option).
+    pub previews: Option<PathBuf>,
     /// Path to the web app (directory containing index.html).
     pub web_app: PathBuf,
     /// The path to the persisted playlist file. Will be created if it doesn't exist.
@@ -0,0 +1,249 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, Rgb, RgbImage};
+
+/// The sizes thumbnails are generated in, in pixels (the bounding box, the aspect ratio is kept).
+/// Requested sizes are rounded up to the next one, so the cache doesn't grow with arbitrary sizes.
+const COVER_SIZES: [u32; 3] = [128, 256, 512];
+const DEFAULT_SIZE: u32 = 256;
+const JPEG_QUALITY: u8 = 80;
+
+/// Returns the fixed thumbnail size to use for the requested size.
+pub fn thumbnail_size(requested: Option<u32>) -> u32 {
+    let requested = requested.unwrap_or(DEFAULT_SIZE);
+    COVER_SIZES
+        .into_iter()
+        .find(|&size| size >= requested)
+        .unwrap_or(COVER_SIZES[COVER_SIZES.len() - 1])
+}
+
+/// A JPEG thumbnail, along with a tag that changes whenever its content does.
+pub struct Thumbnail {
+    pub tag: String,
+    pub data: Vec<u8>,
+}
+
+/// Generates cover thumbnails on demand and keeps them on disk, so that phones don't have to
+/// download the original images.
+///
+/// Thumbnails are stored by their tag, so a cover that's replaced gets a new thumbnail, and the
+/// old one is deleted by [`CoverCache::prune`].
+#[derive(Clone)]
+pub struct CoverCache {
+    dir: PathBuf,
+    media: PathBuf,
+}
+
+impl CoverCache {
+    pub fn new(dir: PathBuf, media: PathBuf) -> anyhow::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, media })
+    }
+
+    /// Returns the thumbnail of the cover at `cover_path` (relative to the media directory), or a
+    /// placeholder if the song has no cover or it can't be read. This blocks on file IO and image
+    /// processing.
+    pub fn thumbnail(
+        &self,
+        song_id: &str,
+        cover_path: Option<&Path>,
+        size: u32,
+    ) -> anyhow::Result<Thumbnail> {
+        let Some(cover_path) = cover_path else {
+            return self.placeholder(song_id, size);
+        };
+        let full_path = self.media.join(cover_path);
+        let result = (|| -> anyhow::Result<Thumbnail> {
+            let tag = thumbnail_tag(&cover_version(&full_path)?, size);
+            self.cached(tag, || Ok(image::open(&full_path)?.thumbnail(size, size)))
+        })();
+        result.or_else(|err| {
+            log::warn!("Failed generating the thumbnail of {full_path:?}: {err:?}");
+            self.placeholder(song_id, size)
+        })
+    }
+
+    fn placeholder(&self, song_id: &str, size: u32) -> anyhow::Result<Thumbnail> {
+        let tag = placeholder_tag(song_id, size);
+        self.cached(tag, || Ok(placeholder_image(song_id, size)))
+    }
+
+    /// Deletes the thumbnails of covers that were replaced and of songs that were removed, and
+    /// returns how many. This blocks on file IO for every cover.
+    pub fn prune(
+        &self,
+        songs: &HashSet<String>,
+        cover_paths: &HashMap<String, PathBuf>,
+    ) -> anyhow::Result<usize> {
+        let mut current = HashSet::new();
+        for song_id in songs {
+            let version = cover_paths
+                .get(song_id)
+                .and_then(|cover_path| cover_version(&self.media.join(cover_path)).ok());
+            for size in COVER_SIZES {
+                current.insert(placeholder_tag(song_id, size));
+                if let Some(version) = &version {
+                    current.insert(thumbnail_tag(version, size));
+                }
+            }
+        }
+
+        let mut count = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            // Temporary files are left alone, they might belong to a thumbnail that's being
+            // written right now.
+            if path.extension().is_some_and(|ext| ext == "jpg")
+                && path
+                    .file_stem()
+                    .and_then(|tag| tag.to_str())
+                    .is_some_and(|tag| !current.contains(tag))
+            {
+                fs::remove_file(&path)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    fn cached(
+        &self,
+        tag: String,
+        generate: impl FnOnce() -> anyhow::Result<DynamicImage>,
+    ) -> anyhow::Result<Thumbnail> {
+        let path = self.dir.join(format!("{tag}.jpg"));
+        if let Ok(data) = fs::read(&path) {
+            return Ok(Thumbnail { tag, data });
+        }
+
+        let image = generate()?;
+        let mut data = Vec::new();
+        JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY).encode_image(&image.to_rgb8())?;
+        // Concurrent requests for the same thumbnail must never see a partially written file.
+        let temp_path = self.dir.join(format!("{tag}.{}.tmp", uuid::Uuid::new_v4()));
+        fs::write(&temp_path, &data)?;
+        fs::rename(&temp_path, &path)?;
+        Ok(Thumbnail { tag, data })
+    }
+}
+
+/// Identifies the current content of a cover file. The cover can be replaced by a re-import
+/// without the song id changing.
+fn cover_version(full_path: &Path) -> anyhow::Result<String> {
+    let metadata = fs::metadata(full_path)?;
+    let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos();
+    Ok(format!(
+        "{}:{mtime}:{}",
+        full_path.display(),
+        metadata.len()
+    ))
+}
+
+fn thumbnail_tag(cover_version: &str, size: u32) -> String {
+    sha256::digest(format!("{cover_version}:{size}"))
+}
+
+fn placeholder_tag(song_id: &str, size: u32) -> String {
+    sha256::digest(format!("placeholder:{song_id}:{size}"))
+}
+
+/// A diagonal gradient in colors derived from the song id, so that songs without a cover can
+/// still be told apart.
+fn placeholder_image(song_id: &str, size: u32) -> DynamicImage {
+    let hash = sha256::digest(song_id);
+    let channel = |idx: usize| {
+        let value = u8::from_str_radix(&hash[idx * 2..idx * 2 + 2], 16).unwrap();
+        // Neither too dark nor too bright.
+        64.0 + f32::from(value) / 2.0
+    };
+    let start = [channel(0), channel(1), channel(2)];
+    let end = [channel(3), channel(4), channel(5)];
+    RgbImage::from_fn(size, size, |x, y| {
+        let t = (x + y) as f32 / (2 * size) as f32;
+        Rgb(std::array::from_fn(|idx| {
+            (start[idx] + (end[idx] - start[idx]) * t) as u8
+        }))
+    })
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for the files of one test.
+    fn test_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cover-cache-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    fn cached_files(dir: &Path) -> usize {
+        fs::read_dir(dir).unwrap().count()
+    }
+
+    #[test]
+    fn rounds_sizes_up() {
+        assert_eq!(thumbnail_size(None), 256);
+        assert_eq!(thumbnail_size(Some(0)), 128);
+        assert_eq!(thumbnail_size(Some(129)), 256);
+        assert_eq!(thumbnail_size(Some(5000)), 512);
+    }
+
+    #[test]
+    fn replaced_covers_get_new_thumbnails() {
+        let dir = test_dir();
+        let media = dir.join("media");
+        fs::create_dir(&media).unwrap();
+        let covers = CoverCache::new(dir.join("cache"), media.clone()).unwrap();
+        let songs = HashSet::from(["a".to_owned(), "b".to_owned()]);
+        let cover_paths = HashMap::from([("a".to_owned(), PathBuf::from("a.png"))]);
+
+        RgbImage::new(4, 4).save(media.join("a.png")).unwrap();
+        let old = covers
+            .thumbnail("a", Some(Path::new("a.png")), 128)
+            .unwrap();
+        covers.thumbnail("b", None, 128).unwrap();
+        assert_eq!(covers.prune(&songs, &cover_paths).unwrap(), 0);
+
+        RgbImage::from_pixel(16, 16, Rgb([255, 0, 0]))
+            .save(media.join("a.png"))
+            .unwrap();
+        let new = covers
+            .thumbnail("a", Some(Path::new("a.png")), 128)
+            .unwrap();
+        assert_ne!(old.tag, new.tag);
+        assert_ne!(old.data, new.data);
+
+        // The old thumbnail of a and the placeholder of the removed song b are deleted.
+        let songs = HashSet::from(["a".to_owned()]);
+        assert_eq!(covers.prune(&songs, &cover_paths).unwrap(), 2);
+        assert_eq!(cached_files(&dir.join("cache")), 1);
+        assert_eq!(
+            covers
+                .thumbnail("a", Some(Path::new("a.png")), 128)
+                .unwrap()
+                .tag,
+            new.tag
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_covers_get_placeholders() {
+        let dir = test_dir();
+        let covers = CoverCache::new(dir.join("cache"), dir.join("media")).unwrap();
+        let missing = covers
+            .thumbnail("a", Some(Path::new("missing.png")), 128)
+            .unwrap();
+        let placeholder = covers.thumbnail("a", None, 128).unwrap();
+        assert_eq!(missing.tag, placeholder.tag);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use icu_experimental::displaynames::{DisplayNamesOptions, LanguageDisplayNames};
+use icu_locid::{locale, subtags::Language, Locale};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct LanguageEntry {
+    /// ISO 639-1 code, or ISO 639-3 for languages without one.
+    pub code: String,
+    pub name: String,
+    pub count: usize,
+}
+
+/// Lists the languages with their names in the given locale (English by default), sorted by
+/// name. Languages without a name in the locale are listed by their code.
+pub fn list(counts: &HashMap<String, usize>, locale: Option<&str>) -> Vec<LanguageEntry> {
+    let options = DisplayNamesOptions::default();
+    // Locales without data, or that aren't valid at all, fall back to English.
+    let names = locale
+        .and_then(|locale| locale.parse::<Locale>().ok())
+        .and_then(|locale| LanguageDisplayNames::try_new(&(&locale).into(), options).ok())
+        .or_else(|| LanguageDisplayNames::try_new(&locale!("en").into(), options).ok());
+
+    let mut languages: Vec<_> = counts
+        .iter()
+        .map(|(code, &count)| {
+            let name = names
+                .as_ref()
+                .zip(code.parse::<Language>().ok())
+                .and_then(|(names, language)| names.of(language))
+                .unwrap_or(code);
+            LanguageEntry {
+                code: code.clone(),
+                name: name.to_owned(),
+                count,
+            }
+        })
+        .collect();
+    languages.sort_by_cached_key(|language| language.name.to_lowercase());
+    languages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts() -> HashMap<String, usize> {
+        HashMap::from([
+            ("de".to_owned(), 3),
+            ("en".to_owned(), 5),
+            ("qaa".to_owned(), 1),
+        ])
+    }
+
+    #[test]
+    fn names_languages_in_the_locale() {
+        let languages = list(&counts(), Some("de"));
+        let names: Vec<_> = languages
+            .iter()
+            .map(|language| {
+                (
+                    language.code.as_str(),
+                    language.name.as_str(),
+                    language.count,
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("de", "Deutsch", 3),
+                ("en", "Englisch", 5),
+                ("qaa", "qaa", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_english() {
+        for locale in [None, Some("xx"), Some("not a locale")] {
+            let languages = list(&counts(), locale);
+            let names: Vec<_> = languages
+                .iter()
+                .map(|language| language.name.as_str())
+                .collect();
+            assert_eq!(names, ["English", "German", "qaa"]);
+        }
+    }
+}
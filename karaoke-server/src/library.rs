@@ -0,0 +1,350 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use rusqlite::{Connection, OpenFlags};
+
+use crate::songs::{urlencode_path, Difficulty, SearchIndex, Song};
+
+/// Everything derived from the song database. It's loaded as a whole and swapped atomically when
+/// the database is reloaded.
+pub struct Library {
+    pub index: SearchIndex,
+    pub song_count: usize,
+    /// The ids of all songs, for validating requests.
+    pub valid_songs: HashSet<String>,
+    /// The covers of all songs that have one, relative to the media directory.
+    pub cover_paths: HashMap<String, PathBuf>,
+    /// The number of songs per ISO 639 language code.
+    pub languages: HashMap<String, usize>,
+    pub genres: HashSet<String>,
+    pub editions: HashSet<String>,
+}
+
+/// Returns where the importer stored the preview clip of the song with the given id.
+pub fn preview_path(previews: &Path, song_id: &str) -> PathBuf {
+    previews.join(format!("{song_id}.m4a"))
+}
+
+impl Library {
+    /// Loads the song database and builds the search index. Also returns the mapping from the
+    /// database rowids to the song ids, for migrating playlists referencing songs by rowid.
+    ///
+    /// The play counts are taken from the song log. Songs played afterwards are counted by
+    /// [`SearchIndex::record_play`].
+    pub fn load(
+        database: &Path,
+        previews: Option<&Path>,
+        song_log: Option<&Path>,
+    ) -> anyhow::Result<(Self, HashMap<i64, String>)> {
+        let song_db: Vec<Song>;
+        let legacy_ids: HashMap<i64, String>;
+        let mut languages: HashMap<String, usize> = HashMap::new();
+        let genres: HashSet<String>;
+        let editions: HashSet<String>;
+        let cover_paths: HashMap<String, PathBuf>;
+        {
+            let mut conn = Connection::open_with_flags(database, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            song_db::check_schema_version(&conn)?;
+            // The importer fills in the ids when it rescans the songs after migrating. Songs
+            // without one can't be referenced, so refuse the database instead of hiding them.
+            let missing_ids: usize = conn.query_row(
+                "SELECT COUNT(*) FROM song WHERE song_id IS NULL",
+                (),
+                |row| row.get(0),
+            )?;
+            if missing_ids > 0 {
+                anyhow::bail!(
+                    "{missing_ids} songs in the song database have no id. Please rerun the importer on it."
+                );
+            }
+            let tx = conn.transaction()?;
+
+            let mut stmt = tx.prepare(
+                "SELECT rowid, song_id, title, artist, language, language_codes, year, duration, lyrics, player_count, loudness, true_peak, lyrics_p1, lyrics_p2, singer_p1, singer_p2, cover_path, audio_path,
+                genre, edition, creator, preview_start, medley_start_beat, medley_end_beat, bpm, gap, video_path, background_path,
+                lowest_pitch, highest_pitch, pitch_range, golden_share, freestyle_share, notes_per_second, duplicate_group, added_at
+                FROM song ORDER BY title COLLATE NOCASE",
+            )?;
+            let mut genre_stmt =
+                tx.prepare("SELECT DISTINCT genre FROM song WHERE genre IS NOT NULL")?;
+            let mut edition_stmt =
+                tx.prepare("SELECT DISTINCT edition FROM song WHERE edition IS NOT NULL")?;
+            let mut cover_stmt =
+                tx.prepare("SELECT song_id, cover_path FROM song WHERE cover_path IS NOT NULL")?;
+            let mut rows: Vec<(i64, Song)> = stmt
+                .query_map((), |row| {
+                    let row_id = row.get("rowid")?;
+                    let cover_path = row.get::<_, Option<Vec<u8>>>("cover_path")?;
+                    let audio_path = row.get::<_, Option<Vec<u8>>>("audio_path")?;
+                    let video_path = row.get::<_, Option<Vec<u8>>>("video_path")?;
+                    let background_path = row.get::<_, Option<Vec<u8>>>("background_path")?;
+                    let pitch_range = row.get("pitch_range")?;
+                    let notes_per_second = row.get("notes_per_second")?;
+                    let language_codes = row.get::<_, Option<String>>("language_codes")?;
+                    let id: String = row.get("song_id")?;
+                    let has_preview =
+                        previews.is_some_and(|previews| preview_path(previews, &id).exists());
+                    let song = Song {
+                        id,
+                        title: row.get("title")?,
+                        artist: row.get("artist")?,
+                        language: row.get("language")?,
+                        language_codes: language_codes
+                            .map(|codes| codes.split(',').map(str::to_owned).collect())
+                            .unwrap_or_default(),
+                        year: row.get("year")?,
+                        duration: row.get("duration")?,
+                        lyrics: row.get("lyrics")?,
+                        duet: row.get::<_, i32>("player_count")? > 1,
+                        loudness: row.get("loudness")?,
+                        true_peak: row.get("true_peak")?,
+                        lyrics_p1: row.get("lyrics_p1")?,
+                        lyrics_p2: row.get("lyrics_p2")?,
+                        singer_p1: row.get("singer_p1")?,
+                        singer_p2: row.get("singer_p2")?,
+                        cover_path: cover_path.map(urlencode_path),
+                        audio_path: urlencode_path(audio_path.unwrap()),
+                        genre: row.get("genre")?,
+                        edition: row.get("edition")?,
+                        creator: row.get("creator")?,
+                        preview_start: row.get("preview_start")?,
+                        has_preview,
+                        medley_start_beat: row.get("medley_start_beat")?,
+                        medley_end_beat: row.get("medley_end_beat")?,
+                        bpm: row.get("bpm")?,
+                        gap: row.get("gap")?,
+                        video_path: video_path.map(urlencode_path),
+                        background_path: background_path.map(urlencode_path),
+                        lowest_pitch: row.get("lowest_pitch")?,
+                        highest_pitch: row.get("highest_pitch")?,
+                        pitch_range,
+                        golden_share: row.get("golden_share")?,
+                        freestyle_share: row.get("freestyle_share")?,
+                        notes_per_second,
+                        difficulty: Difficulty::estimate(pitch_range, notes_per_second),
+                        duplicate_group: row.get("duplicate_group")?,
+                        version_count: None,
+                        primary_version: true,
+                        added_at: row.get("added_at")?,
+                        play_count: 0,
+                    };
+                    Ok((row_id, song))
+                })?
+                .filter_map(|result| match result {
+                    Ok(row) => Some(row),
+                    Err(err) => {
+                        log::error!("Failed loading song: {err:?}");
+                        None
+                    }
+                })
+                .collect();
+            legacy_ids = rows
+                .iter()
+                .map(|(row_id, song)| (*row_id, song.id.clone()))
+                .collect();
+            if let Some(song_log) = song_log {
+                let play_counts = play_counts(song_log, rows.iter().map(|(_, song)| song))?;
+                for (_, song) in &mut rows {
+                    song.play_count = play_counts.get(&song.id).copied().unwrap_or_default();
+                }
+            }
+            song_db = collapse_duplicates(rows);
+            // Like the facets, count every group of duplicates once.
+            for code in song_db
+                .iter()
+                .filter(|song| song.primary_version)
+                .flat_map(|song| &song.language_codes)
+            {
+                *languages.entry(code.clone()).or_default() += 1;
+            }
+            genres = genre_stmt
+                .query_map((), |row| row.get::<_, String>(0))?
+                .collect::<Result<_, _>>()?;
+            editions = edition_stmt
+                .query_map((), |row| row.get::<_, String>(0))?
+                .collect::<Result<_, _>>()?;
+            cover_paths = cover_stmt
+                .query_map((), |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        PathBuf::from(OsStr::from_bytes(&row.get::<_, Vec<u8>>(1)?)),
+                    ))
+                })?
+                .collect::<Result<_, _>>()?;
+        };
+
+        let index = SearchIndex::new(song_db.iter())?;
+        Ok((
+            Self {
+                index,
+                song_count: song_db.len(),
+                valid_songs: song_db.into_iter().map(|song| song.id).collect(),
+                cover_paths,
+                languages,
+                genres,
+                editions,
+            },
+            legacy_ids,
+        ))
+    }
+}
+
+/// Counts how often every song was played according to the song log, which has a row with the
+/// time, artist, title and id for every song that was started.
+fn play_counts<'a>(
+    song_log: &Path,
+    songs: impl IntoIterator<Item = &'a Song>,
+) -> anyhow::Result<HashMap<String, u64>> {
+    // Nothing has been played yet.
+    if !song_log.exists() {
+        return Ok(HashMap::new());
+    }
+    // Songs played before the ids were introduced are only logged with their artist and title.
+    // Duplicates are counted for the first one of them.
+    let mut ids_by_name: HashMap<(&str, &str), &str> = HashMap::new();
+    for song in songs {
+        ids_by_name
+            .entry((&song.artist, &song.title))
+            .or_insert(&song.id);
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(song_log)?;
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                log::warn!("Skipping invalid song log entry: {err}");
+                continue;
+            }
+        };
+        let id = match (record.get(1), record.get(2), record.get(3)) {
+            (_, _, Some(id)) => id,
+            (Some(artist), Some(title), None) => match ids_by_name.get(&(artist, title)) {
+                Some(id) => id,
+                None => continue,
+            },
+            _ => continue,
+        };
+        *counts.entry(id.to_owned()).or_default() += 1;
+    }
+    Ok(counts)
+}
+
+/// Picks the version of every group of duplicates that's shown when duplicates are collapsed,
+/// preferring songs with a cover and a video, and counts the versions.
+fn collapse_duplicates(rows: Vec<(i64, Song)>) -> Vec<Song> {
+    let mut primary: HashMap<i64, (bool, bool, i64)> = HashMap::new();
+    let mut version_counts: HashMap<i64, usize> = HashMap::new();
+    for (row_id, song) in &rows {
+        if let Some(group) = song.duplicate_group {
+            let rank = (
+                song.cover_path.is_none(),
+                song.video_path.is_none(),
+                *row_id,
+            );
+            primary
+                .entry(group)
+                .and_modify(|best| *best = (*best).min(rank))
+                .or_insert(rank);
+            *version_counts.entry(group).or_default() += 1;
+        }
+    }
+
+    rows.into_iter()
+        .map(|(row_id, song)| match song.duplicate_group {
+            Some(group) => Song {
+                version_count: version_counts.get(&group).copied(),
+                primary_version: primary[&group].2 == row_id,
+                ..song
+            },
+            None => song,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for the files of one test.
+    fn test_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("library-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    fn database(dir: &Path) -> (PathBuf, Connection) {
+        let path = dir.join("songs.db");
+        let mut conn = Connection::open(&path).unwrap();
+        song_db::migrate(&mut conn).unwrap();
+        (path, conn)
+    }
+
+    #[test]
+    fn refuses_songs_without_id() {
+        let dir = test_dir();
+        let (path, conn) = database(&dir);
+        conn.execute(
+            "INSERT INTO song (path, song_id, title, artist, duration, audio_path) VALUES (x'61', 'a', 'A', 'Artist', 180.0, x'61'), (x'62', NULL, 'B', 'Artist', 180.0, x'62')",
+            (),
+        )
+        .unwrap();
+        drop(conn);
+        let err = Library::load(&path, None, None).err().unwrap();
+        assert!(err.to_string().contains("1 songs"), "{err}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn counts_languages_of_primary_versions() {
+        let dir = test_dir();
+        let (path, conn) = database(&dir);
+        conn.execute(
+            "INSERT INTO song (path, song_id, title, artist, language_codes, duration, player_count, audio_path, duplicate_group) VALUES (x'61', 'a', 'Halo', 'Beyoncé', 'en', 180.0, 1, x'61', 1), (x'62', 'b', 'Halo', 'Beyoncé', 'en', 180.0, 1, x'62', 1), (x'63', 'c', 'Ça ira', 'Edith Piaf', 'fr,en', 180.0, 1, x'63', NULL)",
+            (),
+        )
+        .unwrap();
+        drop(conn);
+        let (library, _) = Library::load(&path, None, None).unwrap();
+        assert_eq!(library.languages["en"], 2);
+        assert_eq!(library.languages["fr"], 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn counts_plays_from_old_and_new_log_entries() {
+        let dir = test_dir();
+        let (path, conn) = database(&dir);
+        conn.execute(
+            "INSERT INTO song (path, song_id, title, artist, duration, player_count, audio_path) VALUES (x'61', 'a', 'Halo', 'Beyoncé', 180.0, 1, x'61'), (x'62', 'b', 'Ace of Spades', 'Motörhead', 180.0, 1, x'62')",
+            (),
+        )
+        .unwrap();
+        drop(conn);
+        let song_log = dir.join("songs.csv");
+        std::fs::write(
+            &song_log,
+            "2024-01-20T21:03:11.123456Z,Beyoncé,Halo\n\
+             2024-01-20T21:07:45.5Z,Unknown Artist,Unknown Title\n\
+             2024-06-01T20:00:00Z,Beyoncé,Halo,a\n\
+             2024-06-01T20:04:00Z,Motörhead,Ace of Spades,b\n",
+        )
+        .unwrap();
+        let (library, _) = Library::load(&path, None, Some(&song_log)).unwrap();
+        assert_eq!(library.song_count, 2);
+        let play_count = |id: &str| {
+            let songs = library.index.search_song(&format!("id:{id}"), 1).unwrap();
+            songs[0].play_count
+        };
+        assert_eq!(play_count("a"), 2);
+        assert_eq!(play_count("b"), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
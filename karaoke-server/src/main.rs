@@ -1,43 +1,50 @@
 use std::{
-    collections::HashSet,
     net::{IpAddr, Ipv6Addr, SocketAddr},
     path::PathBuf,
-    sync::Arc,
+    sync::{Arc, RwLock},
 };
 
 use axum::{
     body::Body,
-    extract::{Query, State},
-    http::{Request, StatusCode},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, Request, StatusCode},
     middleware::Next,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
+// Unlike axum's `Query`, this one accepts repeated parameters like the facet selections.
+use axum_extra::extract::Query as RepeatedQuery;
 use clap::Parser;
 use csv::{StringRecord, Writer};
 use now_playing::Playlist;
-use rusqlite::{Connection, OpenFlags};
 use serde::Deserialize;
 use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 use tokio::{
     fs::{File, OpenOptions},
     io::AsyncWriteExt,
+    signal::unix::{signal, SignalKind},
     sync::Mutex,
 };
-use tower::ServiceBuilder;
+use tower::{ServiceBuilder, ServiceExt};
 use tower_http::{
-    services::ServeDir,
+    services::{ServeDir, ServeFile},
     trace::{DefaultMakeSpan, TraceLayer},
 };
 
 use crate::{
     config::parse_config,
-    songs::{urlencode_path, SearchIndex, Song},
+    covers::CoverCache,
+    languages::LanguageEntry,
+    library::Library,
+    songs::{FacetCounts, Filters, Page, SortOrder},
     websocket::ws_handler,
 };
 
 mod config;
+mod covers;
+mod languages;
+mod library;
 mod now_playing;
 mod songs;
 mod websocket;
@@ -53,12 +60,57 @@ struct Args {
 }
 
 pub struct AppState {
-    song_count: usize,
-    index: SearchIndex,
+    library: RwLock<Arc<Library>>,
+    database: PathBuf,
+    previews: Option<PathBuf>,
+    song_log: Option<PathBuf>,
+    /// Makes sure that only one reload runs at a time.
+    reload_lock: Mutex<()>,
     playlist: Playlist,
     password: String,
-    languages: HashSet<String>,
     suggest_log: Mutex<File>,
+    covers: CoverCache,
+}
+
+impl AppState {
+    /// The currently loaded songs. Keep the returned handle for the duration of a request, so it
+    /// works on a consistent state even when the library is reloaded in the meantime.
+    pub fn library(&self) -> Arc<Library> {
+        self.library.read().unwrap().clone()
+    }
+
+    /// Loads the song database again and swaps it in, without interrupting connected clients.
+    pub async fn reload_library(&self) -> anyhow::Result<()> {
+        let _guard = self.reload_lock.lock().await;
+        log::info!("Reloading song database...");
+        let database = self.database.clone();
+        let previews = self.previews.clone();
+        let song_log = self.song_log.clone();
+        let (library, _) = tokio::task::spawn_blocking(move || {
+            Library::load(&database, previews.as_deref(), song_log.as_deref())
+        })
+        .await??;
+        let library = Arc::new(library);
+        *self.library.write().unwrap() = library.clone();
+        log::info!(
+            "Reloaded song database, now containing {} songs.",
+            library.song_count
+        );
+        self.prune_covers(library.clone());
+        self.playlist.library_changed(&library).await
+    }
+
+    /// Deletes the cover thumbnails that the library no longer uses in the background.
+    fn prune_covers(&self, library: Arc<Library>) {
+        let covers = self.covers.clone();
+        tokio::task::spawn_blocking(move || {
+            match covers.prune(&library.valid_songs, &library.cover_paths) {
+                Ok(0) => {}
+                Ok(count) => log::info!("Deleted {count} outdated cover thumbnails."),
+                Err(err) => log::warn!("Pruning the cover cache failed: {err:?}"),
+            }
+        });
+    }
 }
 
 async fn add_cors_headers(req: Request<Body>, next: Next) -> impl IntoResponse {
@@ -86,65 +138,34 @@ async fn main() -> anyhow::Result<()> {
     });
 
     log::info!("Loading song database...");
-    let song_db: Vec<Song>;
-    let languages: HashSet<String>;
-    {
-        let mut conn =
-            Connection::open_with_flags(config.paths.database, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
-        let tx = conn.transaction()?;
-
-        let mut stmt = tx.prepare(
-            "SELECT rowid, title, artist, language, year, duration, lyrics, player_count, cover_path, audio_path FROM song ORDER BY title COLLATE NOCASE",
-        )?;
-        let mut lang_stmt =
-            tx.prepare("SELECT DISTINCT language FROM song WHERE LANGUAGE IS NOT NULL")?;
-        song_db = stmt
-            .query_map((), |row| {
-                let row_id = row.get("rowid")?;
-                let cover_path = row.get::<_, Option<Vec<u8>>>("cover_path")?;
-                let audio_path = row.get::<_, Option<Vec<u8>>>("audio_path")?;
-                Ok(Song {
-                    row_id,
-                    title: row.get("title")?,
-                    artist: row.get("artist")?,
-                    language: row.get("language")?,
-                    year: row.get("year")?,
-                    duration: row.get("duration")?,
-                    lyrics: row.get("lyrics")?,
-                    duet: row.get::<_, i32>("player_count")? > 1,
-                    cover_path: cover_path.map(urlencode_path),
-                    audio_path: urlencode_path(audio_path.unwrap()),
-                })
-            })?
-            .filter_map(|result| match result {
-                Ok(song) => Some(song),
-                Err(err) => {
-                    log::error!("Failed loading song: {err:?}");
-                    None
-                }
-            })
-            .collect();
-        languages = lang_stmt
-            .query_map((), |row| row.get::<_, String>(0))?
-            .collect::<Result<_, _>>()?;
-    };
-
-    let index = SearchIndex::new(song_db.iter())?;
-    let song_count = song_db.len();
+    let (library, legacy_ids) = Library::load(
+        &config.paths.database,
+        config.paths.previews.as_deref(),
+        config.paths.song_log.as_deref(),
+    )?;
     let playlist = Playlist::load(
         config.paths.playlist,
-        song_db.iter().map(|song| song.row_id),
+        &library.valid_songs,
+        &legacy_ids,
         config.paths.song_log.as_deref(),
         &config.paths.bug_log,
     )
     .await?;
 
+    let cover_cache = config
+        .paths
+        .cover_cache
+        .unwrap_or_else(|| config.paths.database.with_file_name("cover_cache"));
+    let covers = CoverCache::new(cover_cache, config.paths.media.clone())?;
+
     let state = Arc::new(AppState {
-        song_count,
-        index,
+        library: RwLock::new(Arc::new(library)),
+        database: config.paths.database,
+        previews: config.paths.previews,
+        song_log: config.paths.song_log,
+        reload_lock: Mutex::new(()),
         playlist,
         password: config.server.password,
-        languages,
         suggest_log: Mutex::new(
             OpenOptions::new()
                 .append(true)
@@ -152,6 +173,20 @@ async fn main() -> anyhow::Result<()> {
                 .open(config.paths.suggestion_log)
                 .await?,
         ),
+        covers,
+    });
+    state.prune_covers(state.library());
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            while hangup.recv().await.is_some() {
+                if let Err(err) = state.reload_library().await {
+                    log::error!("Reloading the song database failed: {err:?}");
+                }
+            }
+        }
     });
 
     let app = Router::new()
@@ -160,7 +195,12 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/all_songs", get(get_all_songs))
         .route("/api/random_songs", get(get_random_songs))
         .route("/api/song_count", get(get_song_count))
+        .route("/api/facets", get(get_facets))
         .route("/api/languages", get(get_languages))
+        .route("/api/genres", get(get_genres))
+        .route("/api/editions", get(get_editions))
+        .route("/api/cover/:id", get(get_cover))
+        .route("/api/preview/:id", get(get_preview))
         .route("/api/suggest", post(suggest))
         .route("/ws", get(ws_handler))
         .nest_service("/media", ServeDir::new(config.paths.media))
@@ -190,56 +230,101 @@ async fn get_song(
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     let ids = id
         .split(',')
-        .map(|id| id.parse::<i64>().map(|id| format!("rowid:{id}")))
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|err| {
-            log::error!("Received bad request for song ids {id:?}: {err:?}");
+        .map(|id| {
+            (!id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit()))
+                .then(|| format!("id:{id}"))
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| {
+            log::error!("Received bad request for song ids {id:?}");
             StatusCode::BAD_REQUEST
         })?;
 
-    let result = state.index.search(&ids.join(" OR ")).map_err(|err| {
-        log::error!("Search for songs {ids:?} failed: {err:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    if let Some(song) = result.into_iter().next() {
+    let options = SearchOptions {
+        all_versions: true,
+        cursor: None,
+        per_page: Some(1),
+        sort: None,
+    };
+    let result = state
+        .library()
+        .index
+        .search(&ids.join(" OR "), &options, &Filters::default())
+        .map_err(|err| {
+            log::error!("Search for songs {ids:?} failed: {err:?}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if let Some(song) = result.items.into_iter().next() {
         Ok(Json(song))
     } else {
         Err(StatusCode::NOT_FOUND)
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SearchOptions {
+    /// List every version of songs with duplicates, instead of only the primary one.
+    #[serde(default)]
+    all_versions: bool,
+    /// Where to continue, from the `next` field of the previous page.
+    cursor: Option<String>,
+    per_page: Option<u32>,
+    /// Order the results by this instead of by relevance.
+    sort: Option<SortOrder>,
+}
+
 async fn search(
     State(state): State<Arc<AppState>>,
+    Query(options): Query<SearchOptions>,
+    RepeatedQuery(filters): RepeatedQuery<Filters>,
     search_str: String,
-) -> Result<Json<Vec<serde_json::Value>>, (StatusCode, Body)> {
+) -> Result<Json<Page>, (StatusCode, Body)> {
     log::debug!("Searching for {search_str:?}");
-    let result = state.index.search(&search_str).map_err(|err| {
-        log::error!("Search for {search_str:?} failed: {err:?}");
-        (StatusCode::BAD_REQUEST, Body::from(format!("{err}")))
-    })?;
+    let result = state
+        .library()
+        .index
+        .search(&search_str, &options, &filters)
+        .map_err(|err| {
+            log::error!("Search for {search_str:?} failed: {err:?}");
+            (StatusCode::BAD_REQUEST, Body::from(format!("{err}")))
+        })?;
     Ok(Json(result))
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Pagination {
+    /// Ignored if there's a cursor.
+    #[serde(default)]
     offset: u32,
+    /// Where to continue, from the `next` field of the previous page.
+    cursor: Option<String>,
     per_page: u32,
     query: Option<String>,
+    /// List every version of songs with duplicates, instead of only the primary one.
+    #[serde(default)]
+    all_versions: bool,
+    #[serde(default)]
+    sort: SortOrder,
 }
 
 async fn get_all_songs(
     State(state): State<Arc<AppState>>,
     Query(pagination): Query<Pagination>,
-) -> Result<Json<Vec<serde_json::Value>>, StatusCode> {
-    let result = state.index.paginated(pagination).map_err(|err| {
-        log::error!("Fetching paginated failed: {err:?}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    RepeatedQuery(filters): RepeatedQuery<Filters>,
+) -> Result<Json<Page>, (StatusCode, Body)> {
+    let result = state
+        .library()
+        .index
+        .paginated(pagination, &filters)
+        .map_err(|err| {
+            log::error!("Fetching paginated failed: {err:?}");
+            (StatusCode::BAD_REQUEST, Body::from(format!("{err}")))
+        })?;
     Ok(Json(result))
 }
 
 async fn get_song_count(State(state): State<Arc<AppState>>) -> String {
-    state.song_count.to_string()
+    state.library().song_count.to_string()
 }
 
 #[derive(Debug, Deserialize)]
@@ -253,6 +338,7 @@ async fn get_random_songs(
     Query(SongCount { count, query }): Query<SongCount>,
 ) -> Result<Json<Vec<serde_json::Value>>, StatusCode> {
     let result = state
+        .library()
         .index
         .random_picks(count as _, query.as_deref())
         .map_err(|err| {
@@ -262,10 +348,155 @@ async fn get_random_songs(
     Ok(Json(result))
 }
 
-async fn get_languages(State(state): State<Arc<AppState>>) -> Json<Vec<String>> {
-    let mut languages: Vec<_> = state.languages.iter().cloned().collect();
-    languages.sort();
-    Json(languages)
+#[derive(Debug, Deserialize)]
+struct FacetQuery {
+    query: Option<String>,
+    /// Count every version of songs with duplicates, instead of only the primary one.
+    #[serde(default)]
+    all_versions: bool,
+}
+
+async fn get_facets(
+    State(state): State<Arc<AppState>>,
+    Query(FacetQuery {
+        query,
+        all_versions,
+    }): Query<FacetQuery>,
+    RepeatedQuery(filters): RepeatedQuery<Filters>,
+) -> Result<Json<FacetCounts>, (StatusCode, Body)> {
+    let result = state
+        .library()
+        .index
+        .facets(query.as_deref(), all_versions, &filters)
+        .map_err(|err| {
+            log::error!("Counting facets for {query:?} failed: {err:?}");
+            (StatusCode::BAD_REQUEST, Body::from(format!("{err}")))
+        })?;
+    Ok(Json(result))
+}
+
+#[derive(Debug, Deserialize)]
+struct DisplayLocale {
+    locale: Option<String>,
+}
+
+async fn get_languages(
+    State(state): State<Arc<AppState>>,
+    Query(DisplayLocale { locale }): Query<DisplayLocale>,
+) -> Json<Vec<LanguageEntry>> {
+    Json(languages::list(
+        &state.library().languages,
+        locale.as_deref(),
+    ))
+}
+
+async fn get_genres(State(state): State<Arc<AppState>>) -> Json<Vec<String>> {
+    let mut genres: Vec<_> = state.library().genres.iter().cloned().collect();
+    genres.sort();
+    Json(genres)
+}
+
+async fn get_editions(State(state): State<Arc<AppState>>) -> Json<Vec<String>> {
+    let mut editions: Vec<_> = state.library().editions.iter().cloned().collect();
+    editions.sort();
+    Json(editions)
+}
+
+#[derive(Debug, Deserialize)]
+struct CoverSize {
+    size: Option<u32>,
+}
+
+async fn get_cover(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(CoverSize { size }): Query<CoverSize>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let library = state.library();
+    if !library.valid_songs.contains(&id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let cover_path = library.cover_paths.get(&id).cloned();
+    let size = covers::thumbnail_size(size);
+
+    let thumbnail = tokio::task::spawn_blocking({
+        let state = state.clone();
+        move || state.covers.thumbnail(&id, cover_path.as_deref(), size)
+    })
+    .await
+    .map_err(|err| {
+        log::error!("Generating a cover thumbnail panicked: {err:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .map_err(|err| {
+        log::error!("Generating a cover thumbnail failed: {err:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let etag = format!("\"{}\"", thumbnail.tag);
+    let response_headers = [
+        (header::CONTENT_TYPE, "image/jpeg".to_owned()),
+        // The URL stays the same when the cover is replaced, so browsers have to check the tag.
+        (header::CACHE_CONTROL, "public, no-cache".to_owned()),
+        (header::ETAG, etag.clone()),
+    ];
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| value.as_bytes() == etag.as_bytes())
+    {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+    Ok((response_headers, thumbnail.data).into_response())
+}
+
+async fn get_preview(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    request: Request<Body>,
+) -> Result<Response, StatusCode> {
+    let Some(previews) = &state.previews else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    // Also makes sure that the id can't be used to escape the preview directory.
+    if !state.library().valid_songs.contains(&id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let path = library::preview_path(previews, &id);
+    let metadata = tokio::fs::metadata(&path)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let etag = preview_etag(&metadata).map_err(|err| {
+        log::error!("Reading the modification time of {path:?} failed: {err:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let response_headers = [
+        // Re-imports replace the clip under the same URL, so browsers have to check the tag.
+        (header::CACHE_CONTROL, "public, no-cache".to_owned()),
+        (header::ETAG, etag.clone()),
+    ];
+    if request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| value.as_bytes() == etag.as_bytes())
+    {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+    // Handles range requests, which browsers use for audio.
+    let response = ServeFile::new(path)
+        .oneshot(request)
+        .await
+        .unwrap_or_else(|err| match err {});
+    Ok((response_headers, response).into_response())
+}
+
+/// Identifies the version of a preview clip by its modification time and size.
+fn preview_etag(metadata: &std::fs::Metadata) -> anyhow::Result<String> {
+    let mtime = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_nanos();
+    Ok(format!("\"{mtime:x}-{:x}\"", metadata.len()))
 }
 
 #[derive(Debug, Deserialize)]
@@ -15,7 +15,7 @@ use tokio::{
 };
 use uuid::Uuid;
 
-use crate::songs::SearchIndex;
+use crate::library::Library;
 
 const MAX_PLAY_HISTORY: usize = 3;
 
@@ -23,7 +23,7 @@ const MAX_PLAY_HISTORY: usize = 3;
 #[serde(rename_all = "camelCase")]
 pub struct PlaylistEntry {
     id: Uuid,
-    song: i64,
+    song: String,
     singer: String,
     password_hash: Option<String>,
     #[serde(with = "time::serde::rfc3339")]
@@ -43,7 +43,6 @@ struct InnerPlaylist {
 
 #[derive(Debug)]
 pub struct Playlist {
-    valid_songs: HashSet<i64>,
     song_queue: RwLock<InnerPlaylist>,
     persist_path: PathBuf,
     song_log: Option<Mutex<File>>,
@@ -53,7 +52,8 @@ pub struct Playlist {
 impl Playlist {
     pub async fn load(
         path: impl AsRef<Path>,
-        valid_songs: impl IntoIterator<Item = i64>,
+        valid_songs: &HashSet<String>,
+        legacy_ids: &HashMap<i64, String>,
         song_log: Option<impl AsRef<Path>>,
         bug_log: impl AsRef<Path>,
     ) -> anyhow::Result<Self> {
@@ -81,8 +81,9 @@ impl Playlist {
             Ok(mut f) => {
                 let mut data = Vec::new();
                 f.read_to_end(&mut data).await?;
-                let valid_songs: HashSet<_> = valid_songs.into_iter().collect();
-                let mut song_queue: InnerPlaylist = serde_json::from_slice(&data)?;
+                let mut json: serde_json::Value = serde_json::from_slice(&data)?;
+                Self::migrate_legacy_ids(&mut json, legacy_ids);
+                let mut song_queue: InnerPlaylist = serde_json::from_value(json)?;
 
                 // Don't keep songs in the list that no longer exist.
                 song_queue
@@ -93,7 +94,6 @@ impl Playlist {
                     .retain(|entry| valid_songs.contains(&entry.song));
 
                 Ok(Self {
-                    valid_songs,
                     song_queue: RwLock::new(song_queue),
                     persist_path: path.as_ref().to_owned(),
                     song_log,
@@ -101,7 +101,6 @@ impl Playlist {
                 })
             }
             Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self {
-                valid_songs: valid_songs.into_iter().collect(),
                 song_queue: Default::default(),
                 persist_path: path.as_ref().to_owned(),
                 song_log,
@@ -111,6 +110,21 @@ impl Playlist {
         }
     }
 
+    /// Older versions referenced songs by their database rowid instead of the stable song id.
+    fn migrate_legacy_ids(json: &mut serde_json::Value, legacy_ids: &HashMap<i64, String>) {
+        for list in ["list", "playHistory"] {
+            let Some(entries) = json.get_mut(list).and_then(|list| list.as_array_mut()) else {
+                continue;
+            };
+            for entry in entries {
+                if let Some(row_id) = entry.get("song").and_then(|song| song.as_i64()) {
+                    // Unknown songs get an empty id and are dropped as invalid.
+                    entry["song"] = legacy_ids.get(&row_id).cloned().unwrap_or_default().into();
+                }
+            }
+        }
+    }
+
     pub async fn subscribe(&self, listener: UnboundedSender<String>) -> anyhow::Result<Uuid> {
         let mut queue = self.song_queue.write().await;
         listener.send(serde_json::to_string(&*queue).unwrap())?;
@@ -126,17 +140,17 @@ impl Playlist {
 
     pub async fn add(
         &self,
-        song: i64,
+        song: String,
         singer: String,
         password: Option<String>,
-        index: &SearchIndex,
+        library: &Library,
     ) -> anyhow::Result<Option<Uuid>> {
-        if !self.valid_songs.contains(&song) {
+        if !library.valid_songs.contains(&song) {
             return Ok(None);
         }
-        let songs = index.search_song(&format!("rowid:{song}"), 1)?;
+        let songs = library.index.search_song(&format!("id:{song}"), 1)?;
         if songs.is_empty() {
-            log::error!("Can't find song that we should have!");
+            log::error!("Can't find song {song} that we should have!");
             Err(anyhow::anyhow!("Can't find song"))
         } else {
             let mut queue = self.song_queue.write().await;
@@ -155,12 +169,12 @@ impl Playlist {
                 song,
                 predicted_end,
             });
-            Self::did_change(&mut queue, &self.persist_path, index).await?;
+            Self::did_change(&mut queue, &self.persist_path, library).await?;
             Ok(Some(id))
         }
     }
 
-    pub async fn play(&self, id: Uuid, index: &SearchIndex) -> anyhow::Result<bool> {
+    pub async fn play(&self, id: Uuid, library: &Library) -> anyhow::Result<bool> {
         let mut queue = self.song_queue.write().await;
         if let Some(entry) = queue
             .list
@@ -168,7 +182,10 @@ impl Playlist {
             .enumerate()
             .find_map(|(idx, entry)| (entry.id == id).then_some(idx))
         {
-            match index.search_song(&format!("rowid:{}", queue.list[entry].song), 1) {
+            match library
+                .index
+                .search_song(&format!("id:{}", queue.list[entry].song), 1)
+            {
                 Err(err) => {
                     log::error!("Fetching song for song log failed: {err:?}");
                 }
@@ -197,7 +214,7 @@ impl Playlist {
                     }
 
                     // Update playlist and notify listeners
-                    Self::did_change(&mut queue, &self.persist_path, index).await?;
+                    Self::did_change(&mut queue, &self.persist_path, library).await?;
 
                     // Write song log
                     if let Some(song_log) = &self.song_log {
@@ -210,14 +227,14 @@ impl Playlist {
                                 &timestamp,
                                 &songs[0].artist,
                                 &songs[0].title,
+                                &songs[0].id,
                             ]);
                             let mut writer = Writer::from_writer(Vec::new());
                             writer.write_record(&record).unwrap();
 
-                            if let Err(err) =
-                                song_log.write_all(&writer.into_inner().unwrap()).await
-                            {
-                                log::error!("Failed writing song log: {err:?}");
+                            match song_log.write_all(&writer.into_inner().unwrap()).await {
+                                Ok(()) => library.index.record_play(&songs[0].id),
+                                Err(err) => log::error!("Failed writing song log: {err:?}"),
                             }
                         }
                     }
@@ -236,11 +253,11 @@ impl Playlist {
             .find_map(|(idx, entry)| (entry.id == id).then_some(idx))
     }
 
-    pub async fn remove(&self, id: Uuid, index: &SearchIndex) -> anyhow::Result<bool> {
+    pub async fn remove(&self, id: Uuid, library: &Library) -> anyhow::Result<bool> {
         let mut queue = self.song_queue.write().await;
         if let Some(queue_index) = Self::find_song_in_queue(&queue.list, id) {
             queue.list.remove(queue_index);
-            Self::did_change(&mut queue, &self.persist_path, index).await?;
+            Self::did_change(&mut queue, &self.persist_path, library).await?;
             return Ok(true);
         }
         Ok(false)
@@ -250,14 +267,14 @@ impl Playlist {
         &self,
         id: Uuid,
         password: String,
-        index: &SearchIndex,
+        library: &Library,
     ) -> anyhow::Result<bool> {
         let mut queue = self.song_queue.write().await;
         if let Some(queue_index) = Self::find_song_in_queue(&queue.list, id) {
             if let Some(hash) = &queue.list[queue_index].password_hash {
                 if &digest(password) == hash {
                     queue.list.remove(queue_index);
-                    Self::did_change(&mut queue, &self.persist_path, index).await?;
+                    Self::did_change(&mut queue, &self.persist_path, library).await?;
                     return Ok(true);
                 }
             }
@@ -265,7 +282,7 @@ impl Playlist {
         Ok(false)
     }
 
-    pub async fn swap(&self, id1: Uuid, id2: Uuid, index: &SearchIndex) -> anyhow::Result<bool> {
+    pub async fn swap(&self, id1: Uuid, id2: Uuid, library: &Library) -> anyhow::Result<bool> {
         if id1 == id2 {
             return Ok(false);
         }
@@ -283,7 +300,7 @@ impl Playlist {
                 .find_map(|(idx, entry)| (entry.id == id2).then_some(idx))
             {
                 queue.list.swap(entry1, entry2);
-                Self::did_change(&mut queue, &self.persist_path, index).await?;
+                Self::did_change(&mut queue, &self.persist_path, library).await?;
                 return Ok(true);
             }
         }
@@ -294,7 +311,7 @@ impl Playlist {
         &self,
         id: Uuid,
         after: Uuid,
-        index: &SearchIndex,
+        library: &Library,
     ) -> anyhow::Result<bool> {
         if id == after {
             return Ok(false);
@@ -319,14 +336,14 @@ impl Playlist {
                     let entry = queue.list.remove(entry).unwrap();
                     queue.list.insert(after_entry + 1, entry);
                 }
-                Self::did_change(&mut queue, &self.persist_path, index).await?;
+                Self::did_change(&mut queue, &self.persist_path, library).await?;
                 return Ok(true);
             }
         }
         Ok(false)
     }
 
-    pub async fn move_top(&self, id: Uuid, index: &SearchIndex) -> anyhow::Result<bool> {
+    pub async fn move_top(&self, id: Uuid, library: &Library) -> anyhow::Result<bool> {
         let mut queue = self.song_queue.write().await;
         if let Some(entry) = queue
             .list
@@ -336,7 +353,7 @@ impl Playlist {
         {
             let entry = queue.list.remove(entry).unwrap();
             queue.list.push_front(entry);
-            Self::did_change(&mut queue, &self.persist_path, index).await?;
+            Self::did_change(&mut queue, &self.persist_path, library).await?;
             Ok(true)
         } else {
             Ok(false)
@@ -345,23 +362,28 @@ impl Playlist {
 
     pub async fn report_bug(
         &self,
-        song: i64,
+        song: String,
         report: &str,
-        index: &SearchIndex,
+        library: &Library,
     ) -> anyhow::Result<()> {
-        if !self.valid_songs.contains(&song) {
-            log::error!("Bug report for song that doesn't exist!");
+        if !library.valid_songs.contains(&song) {
+            log::error!("Bug report for song {song} that doesn't exist!");
             return Ok(());
         }
-        let songs = index.search_song(&format!("rowid:{song}"), 1)?;
+        let songs = library.index.search_song(&format!("id:{song}"), 1)?;
 
         if songs.is_empty() {
-            log::error!("Can't write bug log: song not found!");
+            log::error!("Can't write bug log: song {song} not found!");
         } else {
             let timestamp = OffsetDateTime::now_utc().format(&Rfc3339).unwrap();
             let mut bug_log = self.bug_log.lock().await;
-            let record =
-                StringRecord::from(vec![&timestamp, &songs[0].artist, &songs[0].title, report]);
+            let record = StringRecord::from(vec![
+                &timestamp,
+                &songs[0].artist,
+                &songs[0].title,
+                report,
+                &songs[0].id,
+            ]);
             let mut writer = Writer::from_writer(Vec::new());
             writer.write_record(&record).unwrap();
 
@@ -372,21 +394,43 @@ impl Playlist {
         Ok(())
     }
 
+    /// Updates the play time estimates for a reloaded song library and sends them to all clients.
+    /// Songs that are no longer in the library are kept in the queue, since someone's waiting for
+    /// them.
+    pub async fn library_changed(&self, library: &Library) -> anyhow::Result<()> {
+        let mut queue = self.song_queue.write().await;
+        for entry in &queue.list {
+            if !library.valid_songs.contains(&entry.song) {
+                log::warn!(
+                    "Song {} for {} is no longer in the song database.",
+                    entry.song,
+                    entry.singer
+                );
+            }
+        }
+        Self::did_change(&mut queue, &self.persist_path, library).await
+    }
+
     async fn did_change(
         inner: &mut InnerPlaylist,
         path: &PathBuf,
-        index: &SearchIndex,
+        library: &Library,
     ) -> anyhow::Result<()> {
         // update play time estimates
-        let songs = index.search_song(
-            &inner
-                .list
-                .iter()
-                .map(|entry| format!("rowid:{}", entry.song))
-                .collect::<Vec<_>>()
-                .join(" OR "),
-            inner.list.len(),
-        )?;
+        // An empty queue would make for an empty query and a search without any results.
+        let songs = if inner.list.is_empty() {
+            Vec::new()
+        } else {
+            library.index.search_song(
+                &inner
+                    .list
+                    .iter()
+                    .map(|entry| format!("id:{}", entry.song))
+                    .collect::<Vec<_>>()
+                    .join(" OR "),
+                inner.list.len(),
+            )?
+        };
         let mut timestamp = inner
             .play_history
             .back()
@@ -397,7 +441,7 @@ impl Playlist {
             .checked_div(inner.intermission_count as _)
             .unwrap_or_default();
         for playlist_item in &mut inner.list {
-            if let Some(song) = songs.iter().find(|&song| song.row_id == playlist_item.song) {
+            if let Some(song) = songs.iter().find(|&song| song.id == playlist_item.song) {
                 timestamp += average_intermission + Duration::seconds_f64(song.duration);
                 playlist_item.predicted_end = timestamp;
             }
@@ -413,3 +457,43 @@ impl Playlist {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for the files of one test.
+    fn test_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("karaoke-server-test-{}", Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    fn empty_library(dir: &Path) -> Library {
+        let database = dir.join("songs.db");
+        let mut conn = rusqlite::Connection::open(&database).unwrap();
+        song_db::migrate(&mut conn).unwrap();
+        drop(conn);
+        Library::load(&database, None, None).unwrap().0
+    }
+
+    #[tokio::test]
+    async fn reload_with_empty_queue() {
+        let dir = test_dir();
+        let library = empty_library(&dir);
+        let playlist = Playlist::load(
+            dir.join("playlist.json"),
+            &library.valid_songs,
+            &HashMap::new(),
+            None::<&Path>,
+            dir.join("bugs.csv"),
+        )
+        .await
+        .unwrap();
+
+        playlist.library_changed(&library).await.unwrap();
+        playlist.library_changed(&library).await.unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
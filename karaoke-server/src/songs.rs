@@ -1,64 +1,453 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::HashMap,
+    ops::Bound,
+    sync::{Arc, RwLock},
+};
+
 use rand::Rng;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tantivy::{
-    collector::{Collector, TopDocs},
-    query::{AllQuery, Query, QueryParser},
-    schema::{Field, Schema, FAST, INDEXED, STORED, STRING, TEXT},
-    DocAddress, DocId, Document, Index, IndexReader, IndexSettings, IndexSortByField,
-    SegmentReader,
+    collector::{Collector, Count, FacetCollector, TopDocs},
+    query::{
+        AllQuery, BooleanQuery, ConstScoreQuery, Occur, Query, QueryParser, RangeQuery, TermQuery,
+    },
+    schema::{
+        Facet, FacetOptions, Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions,
+        FAST, INDEXED, STORED, STRING, TEXT,
+    },
+    tokenizer::{AsciiFoldingFilter, LowerCaser, RemoveLongFilter, SimpleTokenizer, TextAnalyzer},
+    DocAddress, DocId, Document, Index, IndexReader, IndexSettings, IndexSortByField, Searcher,
+    SegmentReader, TantivyError, Term,
 };
 
-use crate::Pagination;
+use crate::{Pagination, SearchOptions};
+
+/// The number of search results per page, if the client doesn't ask for a different number.
+const DEFAULT_SEARCH_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: u32 = 100;
+/// The tokenizer for the fields guests search most, see [`folding_analyzer`].
+const FOLDING_TOKENIZER: &str = "folding";
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Song {
-    pub row_id: i64,
+    /// Stable identifier of the song, which survives re-imports.
+    pub id: String,
     pub title: String,
     pub artist: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub language: Option<String>,
+    /// The ISO 639 codes of the languages, normalized by the importer.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub language_codes: Vec<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub year: Option<i64>,
     pub duration: f64,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub lyrics: Option<String>,
     pub duet: bool,
+    /// Integrated loudness according to EBU R128, in LUFS. Used for normalizing the playback
+    /// volume.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loudness: Option<f64>,
+    /// True peak of the audio, in dBTP.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub true_peak: Option<f64>,
+    /// The lyrics of the first voice of a duet. Only for searching, clients get the whole lyrics.
+    #[serde(skip_serializing)]
+    pub lyrics_p1: Option<String>,
+    /// The lyrics of the second voice of a duet. Only for searching, clients get the whole
+    /// lyrics.
+    #[serde(skip_serializing)]
+    pub lyrics_p2: Option<String>,
+    /// The singer label of the first voice of a duet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub singer_p1: Option<String>,
+    /// The singer label of the second voice of a duet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub singer_p2: Option<String>,
     #[serde(default)]
     pub cover_path: Option<String>,
     pub audio_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edition: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creator: Option<String>,
+    /// Where the preview should start, in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview_start: Option<f64>,
+    /// Whether the importer cut a preview clip, served by `/api/preview/{id}`.
+    pub has_preview: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub medley_start_beat: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub medley_end_beat: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bpm: Option<f64>,
+    /// Delay until the first beat, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gap: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background_path: Option<String>,
+    /// Pitches are in semitones, where 0 is C4.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lowest_pitch: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub highest_pitch: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pitch_range: Option<i64>,
+    /// The share of golden notes in the sung beats, from 0 to 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub golden_share: Option<f64>,
+    /// The share of freestyle notes in the sung beats, from 0 to 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub freestyle_share: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes_per_second: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<Difficulty>,
+    /// Songs that are likely the same recording share a group, see the importer's duplicate
+    /// analysis.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_group: Option<i64>,
+    /// How many versions of the song are in the database, including this one. Only set for
+    /// songs with duplicates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_count: Option<usize>,
+    /// Whether this is the version that's shown when duplicates are collapsed.
+    pub primary_version: bool,
+    /// When the song was first imported, as a Unix timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added_at: Option<i64>,
+    /// How often the song was played, according to the song log.
+    pub play_count: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    /// A rough guess based on how much the voice has to move and how fast the lyrics are.
+    pub fn estimate(pitch_range: Option<i64>, notes_per_second: Option<f64>) -> Option<Self> {
+        let (pitch_range, notes_per_second) = pitch_range.zip(notes_per_second)?;
+        Some(if pitch_range <= 12 && notes_per_second <= 1.5 {
+            Self::Easy
+        } else if pitch_range > 19 || notes_per_second > 3.0 {
+            Self::Hard
+        } else {
+            Self::Medium
+        })
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Easy => "easy",
+            Self::Medium => "medium",
+            Self::Hard => "hard",
+        }
+    }
+}
+
+/// The orders songs can be listed in. Songs that are equal in an order stay in title order, so
+/// pages don't overlap or skip songs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// The order of the library.
+    #[default]
+    Title,
+    /// By artist, then title.
+    Artist,
+    /// Oldest first, songs without a year last.
+    Year,
+    /// Newest first, songs without a year last.
+    YearDesc,
+    /// Shortest first.
+    Duration,
+    /// Most recently added first.
+    Added,
+    /// Most played first.
+    Plays,
+}
+
+impl SortOrder {
+    /// The orders that get their own rank field. The title order is the order of the index.
+    const RANKED: [Self; 5] = [
+        Self::Artist,
+        Self::Year,
+        Self::YearDesc,
+        Self::Duration,
+        Self::Added,
+    ];
+
+    /// The fast field with the position of every song in this order. The play counts change
+    /// while the server runs, so that order is computed for every request instead.
+    fn field_name(self) -> Option<&'static str> {
+        match self {
+            Self::Title => Some("order"),
+            Self::Artist => Some("artist_order"),
+            Self::Year => Some("year_order"),
+            Self::YearDesc => Some("year_desc_order"),
+            Self::Duration => Some("duration_order"),
+            Self::Added => Some("added_order"),
+            Self::Plays => None,
+        }
+    }
+
+    fn compare(self, a: &Song, b: &Song) -> Ordering {
+        match self {
+            Self::Title => Ordering::Equal,
+            Self::Artist => a
+                .artist
+                .chars()
+                .flat_map(char::to_lowercase)
+                .cmp(b.artist.chars().flat_map(char::to_lowercase)),
+            Self::Year => (a.year.is_none(), a.year).cmp(&(b.year.is_none(), b.year)),
+            Self::YearDesc => a
+                .year
+                .is_none()
+                .cmp(&b.year.is_none())
+                .then_with(|| b.year.cmp(&a.year)),
+            Self::Duration => a.duration.total_cmp(&b.duration),
+            Self::Added => a
+                .added_at
+                .is_none()
+                .cmp(&b.added_at.is_none())
+                .then_with(|| b.added_at.cmp(&a.added_at)),
+            Self::Plays => b.play_count.cmp(&a.play_count),
+        }
+    }
+
+    /// Returns the position of every song in this order. `songs` has to be in title order.
+    fn ranks(self, songs: &[&Song]) -> Vec<u64> {
+        let mut sorted: Vec<usize> = (0..songs.len()).collect();
+        // The sort is stable, so ties stay in title order.
+        sorted.sort_by(|&a, &b| self.compare(songs[a], songs[b]));
+        let mut ranks = vec![0; songs.len()];
+        for (rank, index) in sorted.into_iter().enumerate() {
+            ranks[index] = rank as u64;
+        }
+        ranks
+    }
+}
+
+/// Structured filters that are applied on top of the query, as query parameters of `/api/search`,
+/// `/api/all_songs` and `/api/facets`. The bounds are inclusive, durations are in seconds.
+///
+/// The facet selections are repeated query parameters, like `genre=Rock&genre=Pop`, and are
+/// compared case-insensitively. A song has to match one of the selected values of every facet.
+#[derive(Debug, Default, Deserialize)]
+pub struct Filters {
+    year_min: Option<i64>,
+    year_max: Option<i64>,
+    duration_min: Option<f64>,
+    duration_max: Option<f64>,
+    #[serde(default)]
+    language: Vec<String>,
+    #[serde(default)]
+    decade: Vec<String>,
+    #[serde(default)]
+    duet: Vec<String>,
+    #[serde(default)]
+    genre: Vec<String>,
+}
+
+impl Filters {
+    fn selection(&self, facet: FacetKind) -> &[String] {
+        match facet {
+            FacetKind::Language => &self.language,
+            FacetKind::Decade => &self.decade,
+            FacetKind::Duet => &self.duet,
+            FacetKind::Genre => &self.genre,
+        }
+    }
+}
+
+/// The properties songs can be counted by, for narrowing down the search results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FacetKind {
+    Language,
+    Decade,
+    Duet,
+    Genre,
+}
+
+impl FacetKind {
+    const ALL: [Self; 4] = [Self::Language, Self::Decade, Self::Duet, Self::Genre];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Language => "language",
+            Self::Decade => "decade",
+            Self::Duet => "duet",
+            Self::Genre => "genre",
+        }
+    }
+
+    /// The values are lowercased, so that "Rock" and "rock" are counted and selected together.
+    /// [`SearchIndex::facet_values`] keeps how they're displayed.
+    fn facet(self, value: &str) -> Facet {
+        Facet::from_path([self.as_str(), &value.to_lowercase()])
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FacetValue {
+    pub value: String,
+    pub count: u64,
+}
+
+/// The number of songs per facet value. Languages are ISO 639 codes, decades are their first year,
+/// and duets are `true` or `false`.
+#[derive(Debug, Default, Serialize)]
+pub struct FacetCounts {
+    pub language: Vec<FacetValue>,
+    pub decade: Vec<FacetValue>,
+    pub duet: Vec<FacetValue>,
+    pub genre: Vec<FacetValue>,
+}
+
+/// One page of results, with what's needed to fetch the next one.
+#[derive(Debug, Serialize)]
+pub struct Page {
+    /// The number of all results, not only those on this page.
+    pub total: usize,
+    pub items: Vec<serde_json::Value>,
+    /// Pass this as `cursor` to fetch the next page. `None` on the last page.
+    pub next: Option<String>,
 }
 
 pub struct SearchIndex {
-    rowid_field: Field,
+    order_field: Field,
+    id_field: Field,
     title_field: Field,
     artist_field: Field,
     language_field: Field,
+    language_code_field: Field,
     year_field: Field,
     lyrics_field: Field,
     duration_field: Field,
     duet_field: Field,
+    loudness_field: Field,
+    true_peak_field: Field,
+    lyrics_p1_field: Field,
+    lyrics_p2_field: Field,
+    singer_p1_field: Field,
+    singer_p2_field: Field,
     cover_field: Field,
     audio_field: Field,
+    genre_field: Field,
+    edition_field: Field,
+    creator_field: Field,
+    preview_start_field: Field,
+    has_preview_field: Field,
+    medley_start_beat_field: Field,
+    medley_end_beat_field: Field,
+    bpm_field: Field,
+    gap_field: Field,
+    video_field: Field,
+    background_field: Field,
+    lowest_pitch_field: Field,
+    highest_pitch_field: Field,
+    pitch_range_field: Field,
+    golden_share_field: Field,
+    freestyle_share_field: Field,
+    notes_per_second_field: Field,
+    duplicate_group_field: Field,
+    version_count_field: Field,
+    primary_version_field: Field,
+    added_at_field: Field,
+    facet_field: Field,
+    /// The spelling of every facet value as it first appears in title order.
+    facet_values: HashMap<Facet, String>,
+    /// The position of every song in title order, by id.
+    orders: HashMap<String, u64>,
+    /// The play counts in title order. They're kept out of the index, so that playing a song
+    /// counts right away.
+    play_counts: RwLock<Vec<u64>>,
 
     reader: IndexReader,
     query_parser: QueryParser,
 }
 
 impl SearchIndex {
+    /// Builds the index of the songs, which have to be in title order.
     pub fn new<'a>(input: impl IntoIterator<Item = &'a Song>) -> anyhow::Result<Self> {
+        let songs: Vec<&Song> = input.into_iter().collect();
+        // Titles, artists and lyrics are searched without accents, like "Motorhead" for
+        // "Motörhead", since phone keyboards make them tedious to type.
+        let folded_text = TextOptions::default().set_stored().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(FOLDING_TOKENIZER)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
         let mut schema_builder = Schema::builder();
         let order_field = schema_builder.add_u64_field("order", STORED | FAST);
-        let rowid_field = schema_builder.add_i64_field("rowid", INDEXED | STORED);
-        let title_field = schema_builder.add_text_field("title", TEXT | STORED);
-        let artist_field = schema_builder.add_text_field("artist", TEXT | STORED);
+        let id_field = schema_builder.add_text_field("id", STRING | STORED);
+        let title_field = schema_builder.add_text_field("title", folded_text.clone());
+        let artist_field = schema_builder.add_text_field("artist", folded_text.clone());
         let language_field = schema_builder.add_text_field("language", TEXT | STORED);
-        let year_field = schema_builder.add_text_field("year", STRING | STORED);
-        let lyrics_field = schema_builder.add_text_field("lyrics", TEXT | STORED);
-        let duration_field = schema_builder.add_f64_field("duration", STORED);
+        let language_code_field = schema_builder.add_text_field("language_code", STRING | STORED);
+        let year_field = schema_builder.add_i64_field("year", INDEXED | FAST | STORED);
+        // The year again as text, so a bare `1985` finds the songs from 1985.
+        let year_text_field = schema_builder.add_text_field("year_text", STRING);
+        let lyrics_field = schema_builder.add_text_field("lyrics", folded_text.clone());
+        let duration_field = schema_builder.add_f64_field("duration", INDEXED | FAST | STORED);
         let duet_field = schema_builder.add_bool_field("duet", INDEXED | STORED);
+        let loudness_field = schema_builder.add_f64_field("loudness", STORED);
+        let true_peak_field = schema_builder.add_f64_field("true_peak", STORED);
+        let lyrics_p1_field = schema_builder.add_text_field("lyrics_p1", folded_text.clone());
+        let lyrics_p2_field = schema_builder.add_text_field("lyrics_p2", folded_text);
+        let singer_p1_field = schema_builder.add_text_field("singer_p1", TEXT | STORED);
+        let singer_p2_field = schema_builder.add_text_field("singer_p2", TEXT | STORED);
         let cover_field = schema_builder.add_text_field("cover", STORED);
         let audio_field = schema_builder.add_text_field("audio", STORED);
+        let genre_field = schema_builder.add_text_field("genre", TEXT | STORED);
+        let edition_field = schema_builder.add_text_field("edition", TEXT | STORED);
+        let creator_field = schema_builder.add_text_field("creator", TEXT | STORED);
+        let preview_start_field = schema_builder.add_f64_field("preview_start", STORED);
+        let has_preview_field = schema_builder.add_bool_field("has_preview", STORED);
+        let medley_start_beat_field = schema_builder.add_i64_field("medley_start_beat", STORED);
+        let medley_end_beat_field = schema_builder.add_i64_field("medley_end_beat", STORED);
+        let bpm_field = schema_builder.add_f64_field("bpm", STORED);
+        let gap_field = schema_builder.add_f64_field("gap", STORED);
+        let video_field = schema_builder.add_text_field("video", STORED);
+        let background_field = schema_builder.add_text_field("background", STORED);
+        let lowest_pitch_field =
+            schema_builder.add_i64_field("lowest_pitch", INDEXED | FAST | STORED);
+        let highest_pitch_field =
+            schema_builder.add_i64_field("highest_pitch", INDEXED | FAST | STORED);
+        let pitch_range_field =
+            schema_builder.add_i64_field("pitch_range", INDEXED | FAST | STORED);
+        let golden_share_field = schema_builder.add_f64_field("golden_share", STORED);
+        let freestyle_share_field = schema_builder.add_f64_field("freestyle_share", STORED);
+        let notes_per_second_field =
+            schema_builder.add_f64_field("notes_per_second", INDEXED | FAST | STORED);
+        let difficulty_field = schema_builder.add_text_field("difficulty", STRING);
+        let duplicate_group_field =
+            schema_builder.add_i64_field("duplicate_group", INDEXED | STORED);
+        let version_count_field = schema_builder.add_u64_field("version_count", STORED);
+        let primary_version_field =
+            schema_builder.add_bool_field("primary_version", INDEXED | STORED);
+        let added_at_field = schema_builder.add_i64_field("added_at", STORED);
+        let facet_field = schema_builder.add_facet_field("facets", FacetOptions::default());
+        let rank_fields: Vec<_> = SortOrder::RANKED
+            .into_iter()
+            .map(|order| {
+                let field = schema_builder.add_u64_field(order.field_name().unwrap(), FAST);
+                (field, order.ranks(&songs))
+            })
+            .collect();
         let schema = schema_builder.build();
 
         let mut index = Index::builder()
@@ -72,30 +461,139 @@ impl SearchIndex {
             })
             .create_in_ram()?;
         index.set_default_multithread_executor()?;
+        index
+            .tokenizers()
+            .register(FOLDING_TOKENIZER, folding_analyzer());
 
         let mut index_writer = index.writer(50_000_000)?;
 
-        for (order, song) in input.into_iter().enumerate() {
+        let mut facet_values = HashMap::new();
+        let mut add_facet = |doc: &mut Document, kind: FacetKind, value: &str| {
+            let facet = kind.facet(value);
+            facet_values
+                .entry(facet.clone())
+                .or_insert_with(|| value.to_owned());
+            doc.add_facet(facet_field, facet);
+        };
+        for (order, song) in songs.iter().enumerate() {
             let mut doc = Document::new();
-            doc.add_i64(rowid_field, song.row_id);
+            doc.add_text(id_field, &song.id);
             doc.add_u64(order_field, order as _);
+            for (field, ranks) in &rank_fields {
+                doc.add_u64(*field, ranks[order]);
+            }
             doc.add_text(title_field, song.title.clone());
             doc.add_text(artist_field, song.artist.clone());
-            doc.add_f64(duration_field, song.duration as _);
+            doc.add_f64(duration_field, song.duration);
             if let Some(song_language) = &song.language {
                 doc.add_text(language_field, song_language.to_owned());
             }
+            for code in &song.language_codes {
+                doc.add_text(language_code_field, code);
+            }
             if let Some(song_year) = song.year {
-                doc.add_text(year_field, song_year.to_string());
+                doc.add_i64(year_field, song_year);
+                doc.add_text(year_text_field, song_year.to_string());
             }
             if let Some(song_lyrics) = &song.lyrics {
                 doc.add_text(lyrics_field, song_lyrics);
             }
             doc.add_bool(duet_field, song.duet);
+            if let Some(loudness) = song.loudness {
+                doc.add_f64(loudness_field, loudness);
+            }
+            if let Some(true_peak) = song.true_peak {
+                doc.add_f64(true_peak_field, true_peak);
+            }
+            if let Some(lyrics_p1) = &song.lyrics_p1 {
+                doc.add_text(lyrics_p1_field, lyrics_p1);
+            }
+            if let Some(lyrics_p2) = &song.lyrics_p2 {
+                doc.add_text(lyrics_p2_field, lyrics_p2);
+            }
+            if let Some(singer_p1) = &song.singer_p1 {
+                doc.add_text(singer_p1_field, singer_p1);
+            }
+            if let Some(singer_p2) = &song.singer_p2 {
+                doc.add_text(singer_p2_field, singer_p2);
+            }
             if let Some(cover) = &song.cover_path {
                 doc.add_text(cover_field, cover);
             }
             doc.add_text(audio_field, &song.audio_path);
+            if let Some(genre) = &song.genre {
+                doc.add_text(genre_field, genre);
+            }
+            if let Some(edition) = &song.edition {
+                doc.add_text(edition_field, edition);
+            }
+            if let Some(creator) = &song.creator {
+                doc.add_text(creator_field, creator);
+            }
+            if let Some(preview_start) = song.preview_start {
+                doc.add_f64(preview_start_field, preview_start);
+            }
+            doc.add_bool(has_preview_field, song.has_preview);
+            if let Some(medley_start_beat) = song.medley_start_beat {
+                doc.add_i64(medley_start_beat_field, medley_start_beat);
+            }
+            if let Some(medley_end_beat) = song.medley_end_beat {
+                doc.add_i64(medley_end_beat_field, medley_end_beat);
+            }
+            if let Some(bpm) = song.bpm {
+                doc.add_f64(bpm_field, bpm);
+            }
+            if let Some(gap) = song.gap {
+                doc.add_f64(gap_field, gap);
+            }
+            if let Some(video) = &song.video_path {
+                doc.add_text(video_field, video);
+            }
+            if let Some(background) = &song.background_path {
+                doc.add_text(background_field, background);
+            }
+            if let Some(lowest_pitch) = song.lowest_pitch {
+                doc.add_i64(lowest_pitch_field, lowest_pitch);
+            }
+            if let Some(highest_pitch) = song.highest_pitch {
+                doc.add_i64(highest_pitch_field, highest_pitch);
+            }
+            if let Some(pitch_range) = song.pitch_range {
+                doc.add_i64(pitch_range_field, pitch_range);
+            }
+            if let Some(golden_share) = song.golden_share {
+                doc.add_f64(golden_share_field, golden_share);
+            }
+            if let Some(freestyle_share) = song.freestyle_share {
+                doc.add_f64(freestyle_share_field, freestyle_share);
+            }
+            if let Some(notes_per_second) = song.notes_per_second {
+                doc.add_f64(notes_per_second_field, notes_per_second);
+            }
+            if let Some(difficulty) = song.difficulty {
+                doc.add_text(difficulty_field, difficulty.as_str());
+            }
+            if let Some(duplicate_group) = song.duplicate_group {
+                doc.add_i64(duplicate_group_field, duplicate_group);
+            }
+            if let Some(version_count) = song.version_count {
+                doc.add_u64(version_count_field, version_count as _);
+            }
+            doc.add_bool(primary_version_field, song.primary_version);
+            if let Some(added_at) = song.added_at {
+                doc.add_i64(added_at_field, added_at);
+            }
+            for code in &song.language_codes {
+                add_facet(&mut doc, FacetKind::Language, code);
+            }
+            if let Some(year) = song.year {
+                let decade = year - year.rem_euclid(10);
+                add_facet(&mut doc, FacetKind::Decade, &decade.to_string());
+            }
+            add_facet(&mut doc, FacetKind::Duet, &song.duet.to_string());
+            if let Some(genre) = &song.genre {
+                add_facet(&mut doc, FacetKind::Genre, genre);
+            }
             index_writer.add_document(doc)?;
         }
 
@@ -103,32 +601,78 @@ impl SearchIndex {
 
         let reader = index.reader()?;
 
+        // Numeric fields can't be searched by default, since the query parser rejects any term
+        // that isn't a number for them. They're queried like `year:1985`, `year:[1980 TO 1989]`
+        // or `duration:<180` instead, only the year has a text copy for bare searches.
         let mut query_parser = QueryParser::for_index(
             &index,
             vec![
                 artist_field,
                 title_field,
                 language_field,
-                year_field,
                 lyrics_field,
+                lyrics_p1_field,
+                lyrics_p2_field,
+                genre_field,
+                edition_field,
+                year_text_field,
             ],
         );
         query_parser.set_field_fuzzy(lyrics_field, false, 2, true);
+        query_parser.set_field_fuzzy(lyrics_p1_field, false, 2, true);
+        query_parser.set_field_fuzzy(lyrics_p2_field, false, 2, true);
         query_parser.set_field_boost(title_field, 3.0);
         query_parser.set_field_boost(artist_field, 2.0);
         query_parser.set_conjunction_by_default();
 
         Ok(Self {
-            rowid_field,
+            order_field,
+            id_field,
             title_field,
             artist_field,
             language_field,
+            language_code_field,
             year_field,
             lyrics_field,
             duration_field,
             duet_field,
+            loudness_field,
+            true_peak_field,
+            lyrics_p1_field,
+            lyrics_p2_field,
+            singer_p1_field,
+            singer_p2_field,
             cover_field,
             audio_field,
+            genre_field,
+            edition_field,
+            creator_field,
+            preview_start_field,
+            has_preview_field,
+            medley_start_beat_field,
+            medley_end_beat_field,
+            bpm_field,
+            gap_field,
+            video_field,
+            background_field,
+            lowest_pitch_field,
+            highest_pitch_field,
+            pitch_range_field,
+            golden_share_field,
+            freestyle_share_field,
+            notes_per_second_field,
+            duplicate_group_field,
+            version_count_field,
+            primary_version_field,
+            added_at_field,
+            facet_field,
+            facet_values,
+            orders: songs
+                .iter()
+                .enumerate()
+                .map(|(order, song)| (song.id.clone(), order as _))
+                .collect(),
+            play_counts: RwLock::new(songs.iter().map(|song| song.play_count).collect()),
             reader,
             query_parser,
         })
@@ -141,14 +685,30 @@ impl SearchIndex {
     ) -> tantivy::Result<Vec<Song>> {
         let searcher = self.reader.searcher();
         let results = searcher.search(query, &collector)?;
+        self.load_songs(&searcher, results)
+    }
+
+    /// Loads the songs of the search results from the index.
+    fn load_songs<OrderValue>(
+        &self,
+        searcher: &Searcher,
+        results: Vec<(OrderValue, DocAddress)>,
+    ) -> tantivy::Result<Vec<Song>> {
+        let play_counts = self.play_counts.read().unwrap();
 
         results
             .into_iter()
             .map(|(_, address)| {
                 let song = searcher.doc(address)?;
+                let order = song.get_first(self.order_field).unwrap().as_u64().unwrap();
 
                 let song = Song {
-                    row_id: song.get_first(self.rowid_field).unwrap().as_i64().unwrap(),
+                    id: song
+                        .get_first(self.id_field)
+                        .unwrap()
+                        .as_text()
+                        .unwrap()
+                        .to_owned(),
                     title: song
                         .get_first(self.title_field)
                         .unwrap()
@@ -164,9 +724,13 @@ impl SearchIndex {
                     language: song
                         .get_first(self.language_field)
                         .map(|language| language.as_text().unwrap().to_owned()),
+                    language_codes: song
+                        .get_all(self.language_code_field)
+                        .map(|code| code.as_text().unwrap().to_owned())
+                        .collect(),
                     year: song
                         .get_first(self.year_field)
-                        .map(|year| year.as_text().unwrap().parse().unwrap()),
+                        .and_then(|year| year.as_i64()),
                     duration: song
                         .get_first(self.duration_field)
                         .unwrap()
@@ -179,6 +743,24 @@ impl SearchIndex {
                         .get_first(self.duet_field)
                         .map(|duet| duet.as_bool().unwrap_or_default())
                         .unwrap_or_default(),
+                    loudness: song
+                        .get_first(self.loudness_field)
+                        .and_then(|loudness| loudness.as_f64()),
+                    true_peak: song
+                        .get_first(self.true_peak_field)
+                        .and_then(|peak| peak.as_f64()),
+                    lyrics_p1: song
+                        .get_first(self.lyrics_p1_field)
+                        .map(|lyrics| lyrics.as_text().unwrap().to_owned()),
+                    lyrics_p2: song
+                        .get_first(self.lyrics_p2_field)
+                        .map(|lyrics| lyrics.as_text().unwrap().to_owned()),
+                    singer_p1: song
+                        .get_first(self.singer_p1_field)
+                        .map(|singer| singer.as_text().unwrap().to_owned()),
+                    singer_p2: song
+                        .get_first(self.singer_p2_field)
+                        .map(|singer| singer.as_text().unwrap().to_owned()),
                     cover_path: song
                         .get_first(self.cover_field)
                         .map(|cover| cover.as_text().unwrap().to_owned()),
@@ -186,8 +768,75 @@ impl SearchIndex {
                         .get_first(self.audio_field)
                         .map(|cover| cover.as_text().unwrap().to_owned())
                         .unwrap_or_default(),
+                    genre: song
+                        .get_first(self.genre_field)
+                        .map(|genre| genre.as_text().unwrap().to_owned()),
+                    edition: song
+                        .get_first(self.edition_field)
+                        .map(|edition| edition.as_text().unwrap().to_owned()),
+                    creator: song
+                        .get_first(self.creator_field)
+                        .map(|creator| creator.as_text().unwrap().to_owned()),
+                    preview_start: song
+                        .get_first(self.preview_start_field)
+                        .and_then(|preview_start| preview_start.as_f64()),
+                    has_preview: song
+                        .get_first(self.has_preview_field)
+                        .and_then(|has_preview| has_preview.as_bool())
+                        .unwrap_or_default(),
+                    medley_start_beat: song
+                        .get_first(self.medley_start_beat_field)
+                        .and_then(|beat| beat.as_i64()),
+                    medley_end_beat: song
+                        .get_first(self.medley_end_beat_field)
+                        .and_then(|beat| beat.as_i64()),
+                    bpm: song.get_first(self.bpm_field).and_then(|bpm| bpm.as_f64()),
+                    gap: song.get_first(self.gap_field).and_then(|gap| gap.as_f64()),
+                    video_path: song
+                        .get_first(self.video_field)
+                        .map(|video| video.as_text().unwrap().to_owned()),
+                    background_path: song
+                        .get_first(self.background_field)
+                        .map(|background| background.as_text().unwrap().to_owned()),
+                    lowest_pitch: song
+                        .get_first(self.lowest_pitch_field)
+                        .and_then(|pitch| pitch.as_i64()),
+                    highest_pitch: song
+                        .get_first(self.highest_pitch_field)
+                        .and_then(|pitch| pitch.as_i64()),
+                    pitch_range: song
+                        .get_first(self.pitch_range_field)
+                        .and_then(|range| range.as_i64()),
+                    golden_share: song
+                        .get_first(self.golden_share_field)
+                        .and_then(|share| share.as_f64()),
+                    freestyle_share: song
+                        .get_first(self.freestyle_share_field)
+                        .and_then(|share| share.as_f64()),
+                    notes_per_second: song
+                        .get_first(self.notes_per_second_field)
+                        .and_then(|notes| notes.as_f64()),
+                    difficulty: None,
+                    duplicate_group: song
+                        .get_first(self.duplicate_group_field)
+                        .and_then(|group| group.as_i64()),
+                    version_count: song
+                        .get_first(self.version_count_field)
+                        .and_then(|count| count.as_u64())
+                        .map(|count| count as _),
+                    primary_version: song
+                        .get_first(self.primary_version_field)
+                        .and_then(|primary| primary.as_bool())
+                        .unwrap_or(true),
+                    added_at: song
+                        .get_first(self.added_at_field)
+                        .and_then(|added_at| added_at.as_i64()),
+                    play_count: play_counts[order as usize],
                 };
-                Ok(song)
+                Ok(Song {
+                    difficulty: Difficulty::estimate(song.pitch_range, song.notes_per_second),
+                    ..song
+                })
             })
             .collect()
     }
@@ -197,19 +846,152 @@ impl SearchIndex {
         query: &dyn Query,
         collector: C,
     ) -> tantivy::Result<Vec<serde_json::Value>> {
-        let songs = self.search_internal(query, collector)?;
+        Ok(to_json(self.search_internal(query, collector)?))
+    }
 
-        Ok(songs
-            .into_iter()
-            .map(|song| serde_json::to_value(song).unwrap())
-            .collect())
+    /// Restricts the query to the primary version of every group of duplicates, unless all
+    /// versions were requested.
+    fn collapse_duplicates(&self, query: Box<dyn Query>, all_versions: bool) -> Box<dyn Query> {
+        if all_versions {
+            return query;
+        }
+        let primary = TermQuery::new(
+            Term::from_field_bool(self.primary_version_field, true),
+            IndexRecordOption::Basic,
+        );
+        Box::new(BooleanQuery::new(vec![
+            (Occur::Must, query),
+            filter_clause(primary),
+        ]))
     }
 
-    pub fn search(&self, query: &str) -> tantivy::Result<Vec<serde_json::Value>> {
-        self.search_and_convert(
-            &self.query_parser.parse_query(query)?,
-            TopDocs::with_limit(50),
-        )
+    /// Restricts the query to the songs matching the structured filters, except for the
+    /// selection of the facet `except`.
+    fn apply_filters(
+        &self,
+        query: Box<dyn Query>,
+        filters: &Filters,
+        except: Option<FacetKind>,
+    ) -> Box<dyn Query> {
+        let mut clauses = vec![(Occur::Must, query)];
+        if filters.year_min.is_some() || filters.year_max.is_some() {
+            clauses.push(filter_clause(RangeQuery::new_i64_bounds(
+                "year".to_owned(),
+                filters.year_min.map_or(Bound::Unbounded, Bound::Included),
+                filters.year_max.map_or(Bound::Unbounded, Bound::Included),
+            )));
+        }
+        if filters.duration_min.is_some() || filters.duration_max.is_some() {
+            clauses.push(filter_clause(RangeQuery::new_f64_bounds(
+                "duration".to_owned(),
+                filters
+                    .duration_min
+                    .map_or(Bound::Unbounded, Bound::Included),
+                filters
+                    .duration_max
+                    .map_or(Bound::Unbounded, Bound::Included),
+            )));
+        }
+        for facet in FacetKind::ALL {
+            if Some(facet) == except {
+                continue;
+            }
+            let values: Vec<(Occur, Box<dyn Query>)> = filters
+                .selection(facet)
+                .iter()
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+                .map(|value| {
+                    let term = Term::from_facet(self.facet_field, &facet.facet(value));
+                    let query: Box<dyn Query> =
+                        Box::new(TermQuery::new(term, IndexRecordOption::Basic));
+                    (Occur::Should, query)
+                })
+                .collect();
+            if !values.is_empty() {
+                clauses.push(filter_clause(BooleanQuery::new(values)));
+            }
+        }
+        if clauses.len() == 1 {
+            return clauses.pop().unwrap().1;
+        }
+        Box::new(BooleanQuery::new(clauses))
+    }
+
+    /// Fetches the page of results that `collector` selects, starting at `offset`, and counts
+    /// all results.
+    fn search_page<OrderValue, C: Collector<Fruit = Vec<(OrderValue, DocAddress)>>>(
+        &self,
+        query: &dyn Query,
+        collector: C,
+        offset: usize,
+    ) -> tantivy::Result<Page> {
+        let searcher = self.reader.searcher();
+        let (total, results) = searcher.search(query, &(Count, collector))?;
+        let items = to_json(self.load_songs(&searcher, results)?);
+        let end = offset + items.len();
+        Ok(Page {
+            total,
+            next: (!items.is_empty() && end < total).then(|| end.to_string()),
+            items,
+        })
+    }
+
+    /// Returns the results of the query, ordered by relevance unless another order was requested.
+    pub fn search(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        filters: &Filters,
+    ) -> tantivy::Result<Page> {
+        let offset = cursor_offset(options.cursor.as_deref())?.unwrap_or_default();
+        let per_page = options.per_page.map_or(DEFAULT_SEARCH_PAGE_SIZE, page_size);
+        let query = self.apply_filters(self.query_parser.parse_query(query)?, filters, None);
+        let query = self.collapse_duplicates(query, options.all_versions);
+        match options.sort {
+            Some(sort) => self.sorted_page(&*query, sort, per_page, offset),
+            None => self.search_page(
+                &*query,
+                TopDocs::with_limit(per_page).and_offset(offset),
+                offset,
+            ),
+        }
+    }
+
+    /// Fetches the page of results in the given order that starts at `offset`.
+    fn sorted_page(
+        &self,
+        query: &dyn Query,
+        sort: SortOrder,
+        per_page: usize,
+        offset: usize,
+    ) -> tantivy::Result<Page> {
+        let collector = TopDocs::with_limit(per_page).and_offset(offset);
+        if let Some(field_name) = sort.field_name() {
+            return self.search_page::<u64, _>(
+                query,
+                collector.order_by_fast_field(field_name, tantivy::Order::Asc),
+                offset,
+            );
+        }
+        let play_counts = Arc::new(self.play_counts.read().unwrap().clone());
+        let collector = collector.custom_score(move |segment_reader: &SegmentReader| {
+            let orders = segment_reader.fast_fields().u64("order").unwrap();
+            let play_counts = play_counts.clone();
+            // Most played first, ties in title order.
+            move |doc: DocId| {
+                let order = orders.first(doc).unwrap_or_default();
+                (play_counts[order as usize], Reverse(order))
+            }
+        });
+        self.search_page(query, collector, offset)
+    }
+
+    /// Counts a play of the song, which moves it up in the [`SortOrder::Plays`] order right away.
+    pub fn record_play(&self, song_id: &str) {
+        if let Some(&order) = self.orders.get(song_id) {
+            self.play_counts.write().unwrap()[order as usize] += 1;
+        }
     }
 
     pub fn search_song(&self, query: &str, limit: usize) -> tantivy::Result<Vec<Song>> {
@@ -219,15 +1001,71 @@ impl SearchIndex {
         )
     }
 
-    pub fn paginated(&self, pagination: Pagination) -> tantivy::Result<Vec<serde_json::Value>> {
-        let collector = TopDocs::with_limit(pagination.per_page.min(100) as _)
-            .and_offset(pagination.offset as _)
-            .order_by_fast_field("order", tantivy::Order::Asc);
-        if let Some(query) = &pagination.query {
-            self.search_and_convert::<u64, _>(&self.query_parser.parse_query(query)?, collector)
+    /// Returns the songs matching the query (all songs if there's none) in the requested order,
+    /// by title by default.
+    pub fn paginated(&self, pagination: Pagination, filters: &Filters) -> tantivy::Result<Page> {
+        let offset = cursor_offset(pagination.cursor.as_deref())?.unwrap_or(pagination.offset as _);
+        let query = if let Some(query) = &pagination.query {
+            self.query_parser.parse_query(query)?
         } else {
-            self.search_and_convert::<u64, _>(&AllQuery, collector)
+            Box::new(AllQuery)
+        };
+        let query = self.apply_filters(query, filters, None);
+        self.sorted_page(
+            &*self.collapse_duplicates(query, pagination.all_versions),
+            pagination.sort,
+            page_size(pagination.per_page),
+            offset,
+        )
+    }
+
+    /// Counts the songs matching the query (all songs if there's none) per facet value.
+    ///
+    /// The selection of a facet doesn't narrow down its own counts, only those of the other
+    /// facets. Otherwise, selecting a language would hide all other languages.
+    pub fn facets(
+        &self,
+        query: Option<&str>,
+        all_versions: bool,
+        filters: &Filters,
+    ) -> tantivy::Result<FacetCounts> {
+        let searcher = self.reader.searcher();
+        let mut result = FacetCounts::default();
+        for facet in FacetKind::ALL {
+            let query = if let Some(query) = query {
+                self.query_parser.parse_query(query)?
+            } else {
+                Box::new(AllQuery)
+            };
+            let query = self.collapse_duplicates(
+                self.apply_filters(query, filters, Some(facet)),
+                all_versions,
+            );
+            let mut collector = FacetCollector::for_field("facets");
+            let root = Facet::from_path([facet.as_str()]);
+            collector.add_facet(root.clone());
+            let counts = searcher.search(&*query, &collector)?;
+            let mut values: Vec<_> = counts
+                .get(root)
+                .map(|(value, count)| FacetValue {
+                    value: self.facet_values[value].clone(),
+                    count,
+                })
+                .collect();
+            match facet {
+                FacetKind::Language | FacetKind::Genre => {
+                    values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)))
+                }
+                FacetKind::Decade | FacetKind::Duet => values.sort_by(|a, b| a.value.cmp(&b.value)),
+            }
+            *match facet {
+                FacetKind::Language => &mut result.language,
+                FacetKind::Decade => &mut result.decade,
+                FacetKind::Duet => &mut result.duet,
+                FacetKind::Genre => &mut result.genre,
+            } = values;
         }
+        Ok(result)
     }
 
     pub fn random_picks(
@@ -246,6 +1084,48 @@ impl SearchIndex {
     }
 }
 
+/// Like tantivy's default tokenizer, but also folds accented letters to their ASCII counterparts.
+/// The query parser uses the tokenizer of the field, so queries are folded the same way.
+fn to_json(songs: Vec<Song>) -> Vec<serde_json::Value> {
+    songs
+        .into_iter()
+        .map(|song| serde_json::to_value(song).unwrap())
+        .collect()
+}
+
+fn folding_analyzer() -> TextAnalyzer {
+    TextAnalyzer::builder(SimpleTokenizer::default())
+        .filter(RemoveLongFilter::limit(40))
+        .filter(LowerCaser)
+        .filter(AsciiFoldingFilter)
+        .build()
+}
+
+/// Limits the requested number of results per page. Tantivy doesn't accept empty pages.
+fn page_size(per_page: u32) -> usize {
+    per_page.clamp(1, MAX_PAGE_SIZE) as _
+}
+
+/// Parses the cursor of a page. Cursors are the offset of the page for now, but clients must not
+/// rely on that.
+fn cursor_offset(cursor: Option<&str>) -> tantivy::Result<Option<usize>> {
+    cursor
+        .map(|cursor| {
+            cursor
+                .parse()
+                .map_err(|_| TantivyError::InvalidArgument(format!("Invalid cursor {cursor:?}")))
+        })
+        .transpose()
+}
+
+/// A clause that only filters, without contributing to the score.
+fn filter_clause(query: impl Query) -> (Occur, Box<dyn Query>) {
+    (
+        Occur::Must,
+        Box::new(ConstScoreQuery::new(Box::new(query), 0.0)),
+    )
+}
+
 pub fn urlencode_path(path_bytes: impl IntoIterator<Item = u8>) -> String {
     let mut encoded = String::new();
 
@@ -259,3 +1139,345 @@ pub fn urlencode_path(path_bytes: impl IntoIterator<Item = u8>) -> String {
 
     encoded
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: &str, title: &str, genre: Option<&str>) -> Song {
+        Song {
+            id: id.to_owned(),
+            title: title.to_owned(),
+            artist: "Artist".to_owned(),
+            language: None,
+            language_codes: Vec::new(),
+            year: None,
+            duration: 180.0,
+            lyrics: None,
+            duet: false,
+            loudness: None,
+            true_peak: None,
+            lyrics_p1: None,
+            lyrics_p2: None,
+            singer_p1: None,
+            singer_p2: None,
+            cover_path: None,
+            audio_path: format!("{id}.mp3"),
+            genre: genre.map(str::to_owned),
+            edition: None,
+            creator: None,
+            preview_start: None,
+            has_preview: false,
+            medley_start_beat: None,
+            medley_end_beat: None,
+            bpm: None,
+            gap: None,
+            video_path: None,
+            background_path: None,
+            lowest_pitch: None,
+            highest_pitch: None,
+            pitch_range: None,
+            golden_share: None,
+            freestyle_share: None,
+            notes_per_second: None,
+            difficulty: None,
+            duplicate_group: None,
+            version_count: None,
+            primary_version: true,
+            added_at: None,
+            play_count: 0,
+        }
+    }
+
+    fn search_options() -> SearchOptions {
+        SearchOptions {
+            all_versions: false,
+            cursor: None,
+            per_page: None,
+            sort: None,
+        }
+    }
+
+    fn genre_selection(genres: &[&str]) -> Filters {
+        Filters {
+            genre: genres.iter().map(|&genre| genre.to_owned()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn pagination(sort: SortOrder) -> Pagination {
+        Pagination {
+            offset: 0,
+            cursor: None,
+            per_page: MAX_PAGE_SIZE,
+            query: None,
+            all_versions: false,
+            sort,
+        }
+    }
+
+    fn ids(page: &Page) -> Vec<&str> {
+        page.items
+            .iter()
+            .map(|item| item["id"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn ranks_songs() {
+        let mut a = song("a", "A", None);
+        a.artist = "beta".to_owned();
+        a.year = Some(1990);
+        a.duration = 200.0;
+        let mut b = song("b", "B", None);
+        b.artist = "Alpha".to_owned();
+        b.duration = 150.0;
+        b.added_at = Some(100);
+        let mut c = song("c", "C", None);
+        c.artist = "Beta".to_owned();
+        c.year = Some(1980);
+        c.duration = 200.0;
+        c.added_at = Some(200);
+        let songs = [&a, &b, &c];
+        assert_eq!(SortOrder::Artist.ranks(&songs), [1, 0, 2]);
+        assert_eq!(SortOrder::Year.ranks(&songs), [1, 2, 0]);
+        assert_eq!(SortOrder::YearDesc.ranks(&songs), [0, 2, 1]);
+        assert_eq!(SortOrder::Duration.ranks(&songs), [1, 0, 2]);
+        assert_eq!(SortOrder::Added.ranks(&songs), [2, 1, 0]);
+    }
+
+    #[test]
+    fn counts_plays_right_away() {
+        let mut songs = [
+            song("a", "A", None),
+            song("b", "B", None),
+            song("c", "C", None),
+        ];
+        songs[2].play_count = 1;
+        let index = SearchIndex::new(&songs).unwrap();
+        let page = index
+            .paginated(pagination(SortOrder::Plays), &Filters::default())
+            .unwrap();
+        assert_eq!(ids(&page), ["c", "a", "b"]);
+
+        index.record_play("b");
+        index.record_play("b");
+        index.record_play("unknown");
+        let page = index
+            .paginated(pagination(SortOrder::Plays), &Filters::default())
+            .unwrap();
+        assert_eq!(ids(&page), ["b", "c", "a"]);
+        assert_eq!(page.items[0]["playCount"], 2);
+        let page = index
+            .paginated(pagination(SortOrder::Title), &Filters::default())
+            .unwrap();
+        assert_eq!(ids(&page), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn folds_accents() {
+        let mut analyzer = folding_analyzer();
+        let mut stream = analyzer.token_stream("Motörhead – Ace of Spades (Beyoncé's Señorita)");
+        let mut tokens = Vec::new();
+        while let Some(token) = stream.next() {
+            tokens.push(token.text.clone());
+        }
+        assert_eq!(
+            tokens,
+            [
+                "motorhead",
+                "ace",
+                "of",
+                "spades",
+                "beyonce",
+                "s",
+                "senorita"
+            ]
+        );
+    }
+
+    #[test]
+    fn searches_without_accents() {
+        let mut motorhead = song("1", "Ace of Spades", None);
+        motorhead.artist = "Motörhead".to_owned();
+        let mut beyonce = song("2", "Halo", None);
+        beyonce.artist = "Beyoncé".to_owned();
+        let index = SearchIndex::new(&[motorhead, beyonce]).unwrap();
+        for (query, id) in [("motorhead", "1"), ("MOTÖRHEAD", "1"), ("BEYONCE", "2")] {
+            let page = index
+                .search(query, &search_options(), &Filters::default())
+                .unwrap();
+            assert_eq!(ids(&page), [id], "{query}");
+        }
+    }
+
+    #[test]
+    fn searches_bare_years() {
+        let mut old = song("1", "Old", None);
+        old.year = Some(1985);
+        let mut new = song("2", "New", None);
+        new.year = Some(2015);
+        let index = SearchIndex::new(&[old, new]).unwrap();
+        for (query, id) in [
+            ("1985", "1"),
+            ("year:2015", "2"),
+            ("year:[2000 TO 2020]", "2"),
+        ] {
+            let page = index
+                .search(query, &search_options(), &Filters::default())
+                .unwrap();
+            assert_eq!(ids(&page), [id], "{query}");
+        }
+    }
+
+    #[test]
+    fn searches_lyrics_of_both_voices() {
+        let mut duet = song("1", "Duet", None);
+        duet.duet = true;
+        duet.lyrics = Some("Hello from the first voice".to_owned());
+        duet.lyrics_p1 = Some("Hello from the first voice".to_owned());
+        duet.lyrics_p2 = Some("Goodbye from the second".to_owned());
+        let index = SearchIndex::new(&[duet]).unwrap();
+        let page = index
+            .search("goodbye", &search_options(), &Filters::default())
+            .unwrap();
+        assert_eq!(ids(&page), ["1"]);
+        assert!(page.items[0].get("lyricsP1").is_none());
+        assert!(page.items[0].get("lyricsP2").is_none());
+    }
+
+    #[test]
+    fn parses_cursors() {
+        assert_eq!(cursor_offset(None).unwrap(), None);
+        assert_eq!(cursor_offset(Some("50")).unwrap(), Some(50));
+        assert!(cursor_offset(Some("")).is_err());
+        assert!(cursor_offset(Some("-1")).is_err());
+        assert!(cursor_offset(Some("next")).is_err());
+    }
+
+    #[test]
+    fn pages_through_results() {
+        let songs: Vec<_> = (0..5)
+            .map(|i| song(&i.to_string(), &format!("Song {i}"), None))
+            .collect();
+        let index = SearchIndex::new(&songs).unwrap();
+        let mut options = SearchOptions {
+            per_page: Some(2),
+            ..search_options()
+        };
+        let mut ids = Vec::new();
+        loop {
+            let page = index.search("song", &options, &Filters::default()).unwrap();
+            assert_eq!(page.total, 5);
+            ids.extend(page.items.iter().map(|item| item["id"].clone()));
+            let Some(next) = page.next else {
+                break;
+            };
+            options.cursor = Some(next);
+        }
+        ids.sort_by_key(|id| id.to_string());
+        ids.dedup();
+        assert_eq!(ids.len(), 5);
+    }
+
+    #[test]
+    fn clamps_empty_page_sizes() {
+        let songs = [song("1", "Song One", None), song("2", "Song Two", None)];
+        let index = SearchIndex::new(&songs).unwrap();
+        let options = SearchOptions {
+            per_page: Some(0),
+            ..search_options()
+        };
+        let page = index.search("song", &options, &Filters::default()).unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.next.as_deref(), Some("1"));
+
+        let pagination = Pagination {
+            offset: 0,
+            cursor: None,
+            per_page: 0,
+            query: None,
+            all_versions: false,
+            sort: SortOrder::default(),
+        };
+        let page = index.paginated(pagination, &Filters::default()).unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items.len(), 1);
+    }
+
+    #[test]
+    fn selects_genres_with_commas() {
+        let songs = [
+            song("1", "Song One", Some("Rock, Pop")),
+            song("2", "Song Two", Some("Rock")),
+            song("3", "Song Three", Some("Pop")),
+        ];
+        let index = SearchIndex::new(&songs).unwrap();
+        let page = index
+            .search("song", &search_options(), &genre_selection(&["Rock, Pop"]))
+            .unwrap();
+        assert_eq!(page.total, 1);
+        let page = index
+            .search(
+                "song",
+                &search_options(),
+                &genre_selection(&["Rock", "Pop"]),
+            )
+            .unwrap();
+        assert_eq!(page.total, 2);
+    }
+
+    #[test]
+    fn counts_genres_of_primary_versions() {
+        let mut songs = [
+            song("1", "Song One", Some("Rock")),
+            song("2", "Song One", Some("Rock")),
+            song("3", "Song Two", Some("Pop")),
+        ];
+        for song in &mut songs[..2] {
+            song.duplicate_group = Some(1);
+            song.version_count = Some(2);
+        }
+        songs[1].primary_version = false;
+        let index = SearchIndex::new(&songs).unwrap();
+        let genre_counts = |all_versions| {
+            let counts = index
+                .facets(None, all_versions, &Filters::default())
+                .unwrap();
+            counts
+                .genre
+                .iter()
+                .map(|value| (value.value.clone(), value.count))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            genre_counts(false),
+            [("Pop".to_owned(), 1), ("Rock".to_owned(), 1)]
+        );
+        assert_eq!(
+            genre_counts(true),
+            [("Rock".to_owned(), 2), ("Pop".to_owned(), 1)]
+        );
+    }
+
+    #[test]
+    fn folds_the_case_of_genres() {
+        let songs = [
+            song("1", "Song One", Some("Rock")),
+            song("2", "Song Two", Some("rock")),
+            song("3", "Song Three", Some("Pop")),
+        ];
+        let index = SearchIndex::new(&songs).unwrap();
+        let counts = index.facets(None, false, &Filters::default()).unwrap();
+        let genres: Vec<_> = counts
+            .genre
+            .iter()
+            .map(|value| (value.value.as_str(), value.count))
+            .collect();
+        assert_eq!(genres, [("Rock", 2), ("Pop", 1)]);
+        let page = index
+            .search("song", &search_options(), &genre_selection(&["ROCK"]))
+            .unwrap();
+        assert_eq!(page.total, 2);
+    }
+}
@@ -9,6 +9,7 @@ use axum::{
 };
 use futures_util::{select, FutureExt, SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use tokio::sync::mpsc::unbounded_channel;
 use uuid::Uuid;
 
@@ -20,14 +21,15 @@ use crate::AppState;
 enum Command {
     Authenticate { password: String },
     // If not password is set, tha song CAN NOT be deleted
-    Add { song: i64, singer: String, password: Option<String> },
+    Add { song: String, singer: String, password: Option<String> },
     Play { id: Uuid },
     RemoveAsAdmin { id: Uuid },
     RemoveAsUser { id: Uuid, password: String },
     Swap { id1: Uuid, id2: Uuid },
     MoveAfter { id: Uuid, after: Uuid },
     MoveTop { id: Uuid },
-    ReportBug { song: i64, report: String },
+    ReportBug { song: String, report: String },
+    ReloadSongs,
 }
 
 pub async fn ws_handler(
@@ -43,6 +45,7 @@ async fn handle_socket(socket: WebSocket, who: SocketAddr, state: Arc<AppState>)
     let (mut sender, mut receiver) = socket.split();
 
     let (listen_sender, mut listen_receiver) = unbounded_channel();
+    let (reload_sender, mut reload_receiver) = unbounded_channel::<String>();
     match state.playlist.subscribe(listen_sender).await {
         Err(err) => log::error!("[{who:?}] {err:?}"),
         Ok(subscription) => {
@@ -57,6 +60,12 @@ async fn handle_socket(socket: WebSocket, who: SocketAddr, state: Arc<AppState>)
                     } else {
                         break;
                     },
+                    json = reload_receiver.recv().fuse() => if let Some(json) = json {
+                        if let Err(err) = sender.send(Message::Text(json)).await {
+                            log::error!("[{who:?}] Send failed: {err:?}");
+                            break;
+                        }
+                    },
                     msg = receiver.next().fuse() => match msg {
                         Some(Ok(Message::Text(text))) => {
                             log::debug!("[{who:?}] Received text {text:?}");
@@ -78,29 +87,48 @@ async fn handle_socket(socket: WebSocket, who: SocketAddr, state: Arc<AppState>)
                                             sender.send(Message::Binary(vec![authenticated as u8])).await.map_err(anyhow::Error::from)
                                         }
                                         Command::Add { song, singer, password} => {
-                                            state.playlist.add(song, singer, password, &state.index).await.map(|_| ())
+                                            state.playlist.add(song, singer, password, &state.library()).await.map(|_| ())
                                         }
                                         Command::Play { id } if authenticated => {
-                                            state.playlist.play(id, &state.index).await.map(|_| ())
+                                            state.playlist.play(id, &state.library()).await.map(|_| ())
                                         }
                                         Command::RemoveAsAdmin { id } if authenticated => {
-                                            state.playlist.remove(id, &state.index).await.map(|_| ())
+                                            state.playlist.remove(id, &state.library()).await.map(|_| ())
                                         }
                                         Command::RemoveAsUser { id,password  } => {
-                                            state.playlist.remove_if_password_correct(id, password, &state.index).await.map(|_| ())
+                                            state.playlist.remove_if_password_correct(id, password, &state.library()).await.map(|_| ())
                                         }
 
                                         Command::Swap { id1, id2 } if authenticated => {
-                                            state.playlist.swap(id1, id2, &state.index).await.map(|_| ())
+                                            state.playlist.swap(id1, id2, &state.library()).await.map(|_| ())
                                         }
                                         Command::MoveAfter { id, after } if authenticated => {
-                                            state.playlist.move_after(id, after, &state.index).await.map(|_| ())
+                                            state.playlist.move_after(id, after, &state.library()).await.map(|_| ())
                                         }
                                         Command::MoveTop { id } if authenticated => {
-                                            state.playlist.move_top(id, &state.index).await.map(|_| ())
+                                            state.playlist.move_top(id, &state.library()).await.map(|_| ())
                                         }
                                         Command::ReportBug { song, report } if authenticated => {
-                                            state.playlist.report_bug(song, &report, &state.index).await.map(|_| ())
+                                            state.playlist.report_bug(song, &report, &state.library()).await.map(|_| ())
+                                        }
+                                        Command::ReloadSongs if authenticated => {
+                                            // Reloading takes a while, so it runs in the background to keep handling
+                                            // the MC's commands. The result is sent once it's done.
+                                            let state = state.clone();
+                                            let reload_sender = reload_sender.clone();
+                                            tokio::spawn(async move {
+                                                // A broken database shouldn't disconnect the MC, the old songs are kept in that case.
+                                                let result = match state.reload_library().await {
+                                                    Ok(()) => json!({ "songCount": state.library().song_count }),
+                                                    Err(err) => {
+                                                        log::error!("[{who:?}] Reloading the song database failed: {err:?}");
+                                                        json!({ "error": format!("{err:#}") })
+                                                    }
+                                                };
+                                                // The MC might have disconnected in the meantime.
+                                                let _ = reload_sender.send(json!({ "reload": result }).to_string());
+                                            });
+                                            Ok(())
                                         }
                                         _ => sender.send(Message::Text("Unauthenticated".to_owned())).await.map_err(anyhow::Error::from),
                                     };
@@ -23,6 +23,8 @@ class _AddDialog extends StatefulWidget {
 
 class _AddDialogState extends State<_AddDialog> {
   final _singerController = TextEditingController();
+  // Only used for duets, for the singer of the second voice.
+  final _secondSingerController = TextEditingController();
   final _submitButtonController = RoundedLoadingButtonController();
   var submitting = false;
 
@@ -30,18 +32,20 @@ class _AddDialogState extends State<_AddDialog> {
   void initState() {
     super.initState();
     _singerController.addListener(() => setState(() {}));
+    _secondSingerController.addListener(() => setState(() {}));
   }
 
   @override
   void dispose() {
     _singerController.dispose();
+    _secondSingerController.dispose();
     super.dispose();
   }
 
   Future<void> _submit(BuildContext context) async {
     final state = widget.api.connectionCubit.state;
     if (state is WebSocketConnectedState) {
-      state.submitSong(singer: _singerController.text, songId: widget.song.id);
+      state.submitSong(singer: _singer, songId: widget.song.id);
     }
     _submitButtonController.success();
     if (context.mounted) {
@@ -49,6 +53,17 @@ class _AddDialogState extends State<_AddDialog> {
     }
   }
 
+  String get _singer => widget.song.duet
+      ? '${_singerController.text.trim()} & ${_secondSingerController.text.trim()}'
+      : _singerController.text;
+
+  bool get _canSubmit => widget.song.duet
+      ? _singerController.text.trim().isNotEmpty && _secondSingerController.text.trim().isNotEmpty
+      : _singerController.text.isNotEmpty;
+
+  String _voiceLabel(BuildContext context, int voice, String? singer) => context.t.search.addDialog
+      .duetSingerTextTitle(voice: singer ?? context.t.search.addDialog.duetVoiceName(n: voice));
+
   Size _textSize(String text, TextStyle style) {
     final TextPainter textPainter =
         TextPainter(text: TextSpan(text: text, style: style), maxLines: 1, textDirection: TextDirection.ltr)
@@ -80,7 +95,7 @@ class _AddDialogState extends State<_AddDialog> {
           child: RoundedLoadingButton(
             controller: _submitButtonController,
             color: theme.colorScheme.primary,
-            onPressed: !submitting && _singerController.text.isNotEmpty ? () => _submit(context) : null,
+            onPressed: !submitting && _canSubmit ? () => _submit(context) : null,
             child: Padding(
               padding: const EdgeInsets.symmetric(horizontal: 32),
               child: Text(
@@ -138,13 +153,43 @@ class _AddDialogState extends State<_AddDialog> {
                   }
                 }
                 return TimerBuilder.periodic(const Duration(seconds: 10), builder: (context) {
+                  if (widget.song.duet) {
+                    return Column(
+                      mainAxisSize: MainAxisSize.min,
+                      children: [
+                        TextField(
+                          autofocus: true,
+                          controller: _singerController,
+                          autocorrect: false,
+                          autofillHints: const [AutofillHints.name],
+                          textInputAction: TextInputAction.next,
+                          decoration: InputDecoration(
+                            labelText: _voiceLabel(context, 1, widget.song.singerP1),
+                          ),
+                        ),
+                        TextField(
+                          controller: _secondSingerController,
+                          autocorrect: false,
+                          decoration: InputDecoration(
+                            labelText: _voiceLabel(context, 2, widget.song.singerP2),
+                            helperText: helperText,
+                          ),
+                          onSubmitted: (text) {
+                            if (_canSubmit) {
+                              _submit(context);
+                            }
+                          },
+                        ),
+                      ],
+                    );
+                  }
                   return TextField(
                     autofocus: true,
                     controller: _singerController,
                     autocorrect: false,
                     autofillHints: const [AutofillHints.name],
                     decoration: InputDecoration(
-                      labelText: context.t.search.addDialog.singerTextTitle(n: widget.song.duet ? 2 : 1),
+                      labelText: context.t.search.addDialog.singerTextTitle(n: 1),
                       helperText: helperText,
                     ),
                     onSubmitted: (text) => _submit(context),
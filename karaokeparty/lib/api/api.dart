@@ -5,6 +5,7 @@ import 'package:http/http.dart';
 import 'package:karaokeparty/api/cubit/connection_cubit.dart';
 import 'package:karaokeparty/api/cubit/playlist_cubit.dart';
 import 'package:karaokeparty/model/song.dart';
+import 'package:karaokeparty/model/song_page.dart';
 import 'package:http/http.dart' as http;
 
 import 'package:karaokeparty/api/host_detector.io.dart'
@@ -40,22 +41,25 @@ final class ServerApi {
         WebSocketConnectedState(:final songCount) => songCount,
       };
 
-  Future<List<Song>> search(String text) async {
-    final response = await client.post(Uri.parse('${serverHost.api}/search'), body: utf8.encode(text));
+  Future<SongPage> search(String text, {String? cursor, int? perPage}) async {
+    final uri = Uri.parse('${serverHost.api}/search');
+    final queryParameters = <String, String>{
+      if (cursor != null) 'cursor': cursor,
+      if (perPage != null) 'per_page': perPage.toString(),
+    };
+    final response = await client.post(uri.replace(queryParameters: queryParameters), body: utf8.encode(text));
     if (response.statusCode != 200) {
       throw ServerError(response);
     }
     final json = utf8.decode(response.bodyBytes);
-    return (jsonDecode(json) as List<dynamic>)
-        .map((song) => Song.fromJson(song as Map<String, dynamic>))
-        .toList(growable: false);
+    return SongPage.fromJson(jsonDecode(json) as Map<String, dynamic>);
   }
 
-  Future<List<Song>?> fetchSongs(int offset, int perPage, {SearchFilterCubit? filter}) async {
+  Future<SongPage?> fetchSongs(String? cursor, int perPage, {SearchFilterCubit? filter}) async {
     final uri = Uri.parse('${serverHost.api}/all_songs');
     final query = filter?.queryString(null);
     final queryParameters = <String, String>{
-      'offset': offset.toString(),
+      if (cursor != null) 'cursor': cursor,
       'per_page': perPage.toString(),
       if (query != null) 'query': query,
     };
@@ -64,7 +68,7 @@ final class ServerApi {
       throw ServerError(response);
     }
     final json = utf8.decode(response.bodyBytes);
-    return (jsonDecode(json) as List<dynamic>).map((song) => Song.fromJson(song)).toList(growable: false);
+    return SongPage.fromJson(jsonDecode(json) as Map<String, dynamic>);
   }
 
   Future<Song?> fetchSongByOffset(int offset) async {
@@ -73,7 +77,7 @@ final class ServerApi {
       throw ServerError(response);
     }
     final json = utf8.decode(response.bodyBytes);
-    return (jsonDecode(json) as List<dynamic>).map((song) => Song.fromJson(song)).firstOrNull;
+    return SongPage.fromJson(jsonDecode(json) as Map<String, dynamic>).items.firstOrNull;
   }
 
   Future<List<Song>?> fetchRandomSongs(int count, {String? query}) async {
@@ -90,7 +94,7 @@ final class ServerApi {
     return (jsonDecode(json) as List<dynamic>).map((song) => Song.fromJson(song)).toList(growable: false);
   }
 
-  void submitSong({required String singer, required int songId}) {
+  void submitSong({required String singer, required String songId}) {
     switch (connectionCubit.state) {
       case InitialWebSocketConnectionState():
       case WebSocketConnectingState():
@@ -9,6 +9,7 @@ import 'package:karaokeparty/api/api.dart';
 import 'package:karaokeparty/api/cubit/playlist_cubit.dart';
 import 'package:karaokeparty/i18n/strings.g.dart';
 import 'package:karaokeparty/main.dart';
+import 'package:karaokeparty/model/language.dart';
 import 'package:karaokeparty/model/playlist_entry.dart';
 import 'package:shared_preferences/shared_preferences.dart';
 import 'package:uuid/uuid.dart';
@@ -54,11 +55,12 @@ class ConnectionCubit extends Cubit<WebSocketConnectionState> {
           Exception('Couldn\'t fetch song count, server returned status ${response.statusCode}.')));
       return;
     }
-    final songCount = int.tryParse(response.body);
-    if (songCount == null) {
+    final parsedSongCount = int.tryParse(response.body);
+    if (parsedSongCount == null) {
       emit(WebSocketConnectionFailedState(Exception('Couldn\'t parse song count: ${response.body}')));
       return;
     }
+    var songCount = parsedSongCount;
     final password = sharedPreferences.getString("password");
     if (password != null) {
       channel.sink.add(jsonEncode({
@@ -67,14 +69,17 @@ class ConnectionCubit extends Cubit<WebSocketConnectionState> {
       }));
     }
 
-    final languagesResponse = await client.get(Uri.parse('${serverHost.api}/languages'));
-    if (response.statusCode != 200) {
+    final languagesResponse = await client.get(Uri.parse('${serverHost.api}/languages')
+        .replace(queryParameters: {'locale': LocaleSettings.currentLocale.languageCode}));
+    if (languagesResponse.statusCode != 200) {
       emit(WebSocketConnectionFailedState(
-          Exception('Couldn\'t fetch languages list, server returned status ${response.statusCode}.')));
+          Exception('Couldn\'t fetch languages list, server returned status ${languagesResponse.statusCode}.')));
       return;
     }
 
-    final languages = (jsonDecode(languagesResponse.body) as List).whereType<String>().toList(growable: false);
+    final languages = (jsonDecode(languagesResponse.body) as List)
+        .map((language) => Language.fromJson(language as Map<String, dynamic>))
+        .toList(growable: false);
     emit(WebSocketConnectedState(
         sink: channel.sink, songCount: songCount, isAdmin: false, languages: languages, password: _password));
 
@@ -88,6 +93,18 @@ class ConnectionCubit extends Cubit<WebSocketConnectionState> {
           return;
         }
         log.d('Received websocket message $json');
+        final reload = json['reload'];
+        if (reload is Map<String, dynamic>) {
+          final error = reload['error'];
+          if (error != null) {
+            log.e('Reloading the songs failed: $error');
+          } else if (state case WebSocketConnectedState(:final isAdmin)) {
+            songCount = reload['songCount'] as int;
+            emit(WebSocketConnectedState(
+                sink: channel.sink, songCount: songCount, isAdmin: isAdmin, languages: languages, password: _password));
+          }
+          return;
+        }
         try {
           final playHistoryJson = json['playHistory'];
           final List<PlaylistEntry> playHistory = (playHistoryJson is List<dynamic>)
@@ -24,10 +24,10 @@ final class WebSocketConnectedState extends WebSocketConnectionState {
   final WebSocketSink sink;
   final int songCount;
   final bool isAdmin;
-  final List<String> languages;
+  final List<Language> languages;
   final UuidValue? password;
 
-  void submitSong({required String singer, required int songId}) {
+  void submitSong({required String singer, required String songId}) {
     sink.add(jsonEncode({
       'cmd': 'add',
       'song': songId,
@@ -83,13 +83,19 @@ final class WebSocketConnectedState extends WebSocketConnectionState {
     }));
   }
 
-  void reportBug(int songId, String report) {
+  void reportBug(String songId, String report) {
     sink.add(jsonEncode({
       'cmd': 'reportBug',
       'song': songId,
       'report': report,
     }));
   }
+
+  void reloadSongs() {
+    sink.add(jsonEncode({
+      'cmd': 'reloadSongs',
+    }));
+  }
 }
 
 final class WebSocketConnectionFailedState extends WebSocketConnectionState {
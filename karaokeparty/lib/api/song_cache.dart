@@ -5,9 +5,9 @@ import 'package:karaokeparty/api/api.dart';
 import 'package:karaokeparty/model/song.dart';
 
 final class SongCache {
-  final Map<int, Song?> _cache = {};
+  final Map<String, Song?> _cache = {};
 
-  FutureOr<Song?> get(int id) {
+  FutureOr<Song?> get(String id) {
     final cached = _cache[id];
     if (cached != null) {
       return cached;
@@ -22,7 +22,7 @@ class Browse extends StatefulWidget {
 }
 
 class _BrowseState extends State<Browse> {
-  final _pagingController = PagingController<int, Song>(firstPageKey: 0);
+  final _pagingController = PagingController<String?, Song>(firstPageKey: null);
 
   @override
   void initState() {
@@ -38,17 +38,15 @@ class _BrowseState extends State<Browse> {
     super.dispose();
   }
 
-  Future<void> _fetchPage(int pageKey) async {
+  Future<void> _fetchPage(String? pageKey) async {
     final searchFilter = context.read<SearchFilterCubit>();
     try {
-      final newSongs = await widget.api.fetchSongs(pageKey, _pageSize, filter: searchFilter);
-      if (newSongs != null) {
-        final isLastPage = newSongs.length < _pageSize;
-        if (isLastPage) {
-          _pagingController.appendLastPage(newSongs);
+      final page = await widget.api.fetchSongs(pageKey, _pageSize, filter: searchFilter);
+      if (page != null) {
+        if (page.next == null) {
+          _pagingController.appendLastPage(page.items);
         } else {
-          final nextPageKey = pageKey + newSongs.length;
-          _pagingController.appendPage(newSongs, nextPageKey);
+          _pagingController.appendPage(page.items, page.next);
         }
       }
     } catch (e) {
@@ -82,7 +80,7 @@ class _BrowseState extends State<Browse> {
             _pagingController.refresh();
             _pagingController.value = const PagingState();
           },
-          child: PagedSliverList<int, Song>(
+          child: PagedSliverList<String?, Song>(
             pagingController: _pagingController,
             builderDelegate: PagedChildBuilderDelegate<Song>(
               itemBuilder: (context, item, index) => SongCard(song: item, api: widget.api),
@@ -217,6 +217,22 @@ class _MyAppState extends State<MyApp> {
                               padding: compactLayout ? null : const EdgeInsets.only(right: wideLayoutSidebarWidth),
                             ),
                             actions: [
+                              if (isAdmin)
+                                Padding(
+                                  padding: const EdgeInsets.symmetric(horizontal: 8.0),
+                                  child: Tooltip(
+                                    message: context.t.core.reloadSongsButtonTooltip,
+                                    child: IconButton(
+                                      onPressed: () {
+                                        final connectionState = server.connectionCubit.state;
+                                        if (connectionState is WebSocketConnectedState) {
+                                          connectionState.reloadSongs();
+                                        }
+                                      },
+                                      icon: const Icon(Icons.refresh),
+                                    ),
+                                  ),
+                                ),
                               Padding(
                                 padding: const EdgeInsets.symmetric(horizontal: 8.0),
                                 child: Tooltip(
@@ -0,0 +1,13 @@
+final class Language {
+  /// ISO 639-1 code, or ISO 639-3 for languages without one.
+  final String code;
+  final String name;
+  final int count;
+
+  const Language({required this.code, required this.name, required this.count});
+
+  Language.fromJson(Map<String, dynamic> json)
+      : code = json['code'],
+        name = json['name'],
+        count = json['count'];
+}
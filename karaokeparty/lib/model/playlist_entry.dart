@@ -2,7 +2,7 @@ import 'package:uuid/uuid.dart';
 
 final class PlaylistEntry {
   final UuidValue id;
-  final int song;
+  final String song;
   final String singer;
   final DateTime? predictedEnd;
   final String? passwordHash;
@@ -16,7 +16,7 @@ final class PlaylistEntry {
 
   PlaylistEntry.fromJson(Map<String, dynamic> json)
       : id = UuidValue.fromString(json['id']),
-        song = json['song'] as int,
+        song = json['song'] as String,
         singer = json['singer'],
         predictedEnd = json['predictedEnd'] == null ? null : DateTime.tryParse(json['predictedEnd']),
         passwordHash = json['passwordHash'] as String?;
@@ -1,5 +1,5 @@
 final class Song {
-  final int id;
+  final String id;
   final String title;
   final String artist;
   final String? language;
@@ -7,8 +7,16 @@ final class Song {
   final double duration;
   final String? lyrics;
   final bool duet;
+  final String? singerP1;
+  final String? singerP2;
   final String? coverPath;
   final String audioPath;
+  final String? genre;
+  final String? edition;
+  final double? previewStart;
+  final bool hasPreview;
+  final int? pitchRange;
+  final String? difficulty;
 
   Song({
     required this.id,
@@ -19,12 +27,20 @@ final class Song {
     required this.duration,
     required this.lyrics,
     required this.duet,
+    this.singerP1,
+    this.singerP2,
     required this.coverPath,
     required this.audioPath,
+    this.genre,
+    this.edition,
+    this.previewStart,
+    this.hasPreview = false,
+    this.pitchRange,
+    this.difficulty,
   });
 
   Song.placeholder()
-      : id = -1,
+      : id = '',
         title = 'Let\'s meet at the FooBar sdflkj sdalf ldsjf lksdjf klsjdflsjldkfjlsdj',
         artist = 'Foo Fighters',
         language = null,
@@ -32,11 +48,19 @@ final class Song {
         duration = 260.0,
         lyrics = null,
         duet = false,
+        singerP1 = null,
+        singerP2 = null,
         coverPath = null,
-        audioPath = '';
+        audioPath = '',
+        genre = null,
+        edition = null,
+        previewStart = null,
+        hasPreview = false,
+        pitchRange = null,
+        difficulty = null;
 
   Song.fromJson(Map<String, dynamic> json)
-      : id = json['rowId'],
+      : id = json['id'],
         title = json['title'],
         artist = json['artist'],
         language = json['language'],
@@ -44,6 +68,14 @@ final class Song {
         duration = json['duration'],
         lyrics = json['lyrics'],
         duet = json['duet'] ?? false,
+        singerP1 = json['singerP1'],
+        singerP2 = json['singerP2'],
         coverPath = json['coverPath'],
-        audioPath = json['audioPath'];
+        audioPath = json['audioPath'],
+        genre = json['genre'],
+        edition = json['edition'],
+        previewStart = (json['previewStart'] as num?)?.toDouble(),
+        hasPreview = json['hasPreview'] ?? false,
+        pitchRange = json['pitchRange'] as int?,
+        difficulty = json['difficulty'];
 }
@@ -0,0 +1,19 @@
+import 'package:karaokeparty/model/song.dart';
+
+final class SongPage {
+  /// The number of all results, not only those on this page.
+  final int total;
+  final List<Song> items;
+
+  /// The cursor of the next page, `null` on the last page.
+  final String? next;
+
+  SongPage({required this.total, required this.items, required this.next});
+
+  SongPage.fromJson(Map<String, dynamic> json)
+      : total = json['total'],
+        items = (json['items'] as List<dynamic>)
+            .map((song) => Song.fromJson(song as Map<String, dynamic>))
+            .toList(growable: false),
+        next = json['next'];
+}
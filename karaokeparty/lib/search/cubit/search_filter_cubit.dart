@@ -3,15 +3,25 @@ import 'package:hydrated_bloc/hydrated_bloc.dart';
 part 'search_filter_state.dart';
 
 class SearchFilterCubit extends HydratedCubit<SearchFilterState> {
-  SearchFilterCubit() : super(const SearchFilterState(languages: {}, decade: null, duets: true, singles: true));
+  SearchFilterCubit()
+      : super(const SearchFilterState(
+            languages: {}, decade: null, duets: true, singles: true, difficulty: null, narrowRange: false));
 
   Set<String> get languages => state.languages;
   String? get decade => state.decade;
   bool get duets => state.duets;
   bool get singles => state.singles;
+  String? get difficulty => state.difficulty;
+  bool get narrowRange => state.narrowRange;
 
   set languages(Iterable<String> languages) {
-    emit(SearchFilterState(languages: Set.from(languages), decade: decade, duets: duets, singles: singles));
+    emit(SearchFilterState(
+        languages: Set.from(languages),
+        decade: decade,
+        duets: duets,
+        singles: singles,
+        difficulty: difficulty,
+        narrowRange: narrowRange));
   }
 
   void addLanguage(String language) {
@@ -23,6 +33,8 @@ class SearchFilterCubit extends HydratedCubit<SearchFilterState> {
       decade: decade,
       duets: duets,
       singles: singles,
+      difficulty: difficulty,
+      narrowRange: narrowRange,
     ));
   }
 
@@ -32,6 +44,8 @@ class SearchFilterCubit extends HydratedCubit<SearchFilterState> {
       decade: decade,
       duets: duets,
       singles: singles,
+      difficulty: difficulty,
+      narrowRange: narrowRange,
     ));
   }
 
@@ -44,15 +58,53 @@ class SearchFilterCubit extends HydratedCubit<SearchFilterState> {
   }
 
   set decade(String? decade) {
-    emit(SearchFilterState(languages: languages, decade: decade, duets: duets, singles: singles));
+    emit(SearchFilterState(
+        languages: languages,
+        decade: decade,
+        duets: duets,
+        singles: singles,
+        difficulty: difficulty,
+        narrowRange: narrowRange));
   }
 
   set duets(bool duets) {
-    emit(SearchFilterState(languages: languages, decade: decade, duets: duets, singles: singles));
+    emit(SearchFilterState(
+        languages: languages,
+        decade: decade,
+        duets: duets,
+        singles: singles,
+        difficulty: difficulty,
+        narrowRange: narrowRange));
+  }
+
+  set difficulty(String? difficulty) {
+    emit(SearchFilterState(
+        languages: languages,
+        decade: decade,
+        duets: duets,
+        singles: singles,
+        difficulty: difficulty,
+        narrowRange: narrowRange));
+  }
+
+  set narrowRange(bool narrowRange) {
+    emit(SearchFilterState(
+        languages: languages,
+        decade: decade,
+        duets: duets,
+        singles: singles,
+        difficulty: difficulty,
+        narrowRange: narrowRange));
   }
 
   set singles(bool singles) {
-    emit(SearchFilterState(languages: languages, decade: decade, duets: duets, singles: singles));
+    emit(SearchFilterState(
+        languages: languages,
+        decade: decade,
+        duets: duets,
+        singles: singles,
+        difficulty: difficulty,
+        narrowRange: narrowRange));
   }
 
   String? queryString(String? text) {
@@ -60,25 +112,32 @@ class SearchFilterCubit extends HydratedCubit<SearchFilterState> {
         state.languages.isEmpty &&
         state.decade == null &&
         state.duets == true &&
-        state.singles == true) {
+        state.singles == true &&
+        state.difficulty == null &&
+        !state.narrowRange) {
       return null;
     }
 
     return [
       if (text != null) '($text)',
-      if (state.languages.isNotEmpty) '(${state.languages.map((lang) => 'language:"$lang"').join(' OR ')})',
+      if (state.languages.isNotEmpty) '(${state.languages.map((lang) => 'language_code:"$lang"').join(' OR ')})',
       if (state.decade != null) 'year:[${state.decade!}]',
       if (state.duets && !state.singles) 'duet:true',
       if (!state.duets && state.singles) 'duet:false',
+      if (state.difficulty != null) 'difficulty:${state.difficulty!}',
+      if (state.narrowRange) 'pitch_range:[0 TO 12]',
     ].join(' AND ');
   }
 
   @override
   SearchFilterState fromJson(Map<String, dynamic> json) => SearchFilterState(
-        languages: Set.from(json['languages']),
+        // Older versions stored the language names instead of the ISO 639 codes.
+        languages: Set.from((json['languages'] as List).whereType<String>().where(_isLanguageCode)),
         decade: json['decade'],
         duets: json['duets'] ?? true,
         singles: json['singles'] ?? true,
+        difficulty: json['difficulty'],
+        narrowRange: json['narrowRange'] ?? false,
       );
 
   @override
@@ -87,5 +146,9 @@ class SearchFilterCubit extends HydratedCubit<SearchFilterState> {
         'decade': state.decade,
         'duets': state.duets,
         'singles': state.singles,
+        'difficulty': state.difficulty,
+        'narrowRange': state.narrowRange,
       };
+
+  static bool _isLanguageCode(String language) => RegExp(r'^[a-z]{2,3}$').hasMatch(language);
 }
@@ -1,10 +1,21 @@
 part of 'search_filter_cubit.dart';
 
 final class SearchFilterState {
-  const SearchFilterState({required this.languages, required this.decade, required this.duets, required this.singles});
+  const SearchFilterState({
+    required this.languages,
+    required this.decade,
+    required this.duets,
+    required this.singles,
+    required this.difficulty,
+    required this.narrowRange,
+  });
 
   final Set<String> languages;
   final String? decade;
   final bool singles;
   final bool duets;
+  final String? difficulty;
+
+  /// Only songs with a range of at most one octave.
+  final bool narrowRange;
 }
@@ -3,6 +3,7 @@ import 'dart:ui';
 import 'package:flutter/material.dart';
 import 'package:flutter_bloc/flutter_bloc.dart';
 import 'package:flutter_constraintlayout/flutter_constraintlayout.dart';
+import 'package:infinite_scroll_pagination/infinite_scroll_pagination.dart';
 import 'package:karaokeparty/api/api.dart';
 import 'package:karaokeparty/api/cubit/connection_cubit.dart';
 import 'package:karaokeparty/i18n/strings.g.dart';
@@ -13,6 +14,8 @@ import 'package:karaokeparty/search/suggest_song.dart';
 import 'package:karaokeparty/widgets/filter_bar.dart';
 import 'package:karaokeparty/widgets/song_card.dart';
 
+const _pageSize = 50;
+
 class Search extends StatefulWidget {
   const Search({required this.api, super.key});
 
@@ -23,7 +26,8 @@ class Search extends StatefulWidget {
 }
 
 class _SearchState extends State<Search> {
-  Future<List<Song>>? _searchResults;
+  final _pagingController = PagingController<String?, Song>(firstPageKey: null);
+  String? _search;
   String? _searchedText;
   final _controller = TextEditingController();
   final searchBar = ConstraintId('searchbar');
@@ -35,12 +39,16 @@ class _SearchState extends State<Search> {
     _controller.addListener(() {
       setState(() {});
     });
+    _pagingController.addPageRequestListener((pageKey) {
+      _fetchPage(pageKey);
+    });
     _searchBarFocusNode.requestFocus();
   }
 
   @override
   void dispose() {
     _controller.dispose();
+    _pagingController.dispose();
     _searchBarFocusNode.dispose();
     super.dispose();
   }
@@ -56,9 +64,55 @@ class _SearchState extends State<Search> {
     if (search != null) {
       setState(() {
         _searchedText = text;
-        _searchResults = widget.api.search(search);
+        _search = search;
       });
+      _pagingController.refresh();
+    }
+  }
+
+  Future<void> _fetchPage(String? pageKey) async {
+    final search = _search;
+    if (search == null) {
+      return;
+    }
+    try {
+      final page = await widget.api.search(search, cursor: pageKey, perPage: _pageSize);
+      if (search != _search) {
+        // The search changed while this page was loading.
+        return;
+      }
+      if (page.next == null) {
+        _pagingController.appendLastPage(page.items);
+      } else {
+        _pagingController.appendPage(page.items, page.next);
+      }
+    } catch (e) {
+      _pagingController.error = e;
+    }
+  }
+
+  Widget _errorMessage(BuildContext context, Object? error) {
+    final theme = Theme.of(context);
+
+    final String message;
+    if (error is ServerError && error.response.statusCode == 400) {
+      message = '${context.t.search.searchQueryParserError}\n\n${error.response.body}';
+    } else {
+      message = error.toString();
     }
+
+    return Center(
+      child: Card(
+        child: Padding(
+          padding: const EdgeInsets.all(16),
+          child: Text(
+            message,
+            textAlign: TextAlign.center,
+            style: theme.textTheme.labelLarge!.copyWith(color: theme.colorScheme.error),
+          ),
+        ),
+      ),
+    );
   }
 
   @override
@@ -70,56 +124,24 @@ class _SearchState extends State<Search> {
       child: Stack(
         children: [
           Positioned.fill(
-            child: (_searchResults != null)
-                ? FutureBuilder(
-                    future: _searchResults,
-                    builder: (context, snapshot) {
-                      if (snapshot.hasError) {
-                        final theme = Theme.of(context);
-
-                        final String message;
-                        if (snapshot.error is ServerError &&
-                            (snapshot.error as ServerError).response.statusCode == 400) {
-                          message =
-                              '${context.t.search.searchQueryParserError}\n\n${(snapshot.error as ServerError).response.body}';
-                        } else {
-                          message = snapshot.error.toString();
-                        }
-
-                        return Center(
-                          child: Card(
-                            child: Padding(
-                              padding: const EdgeInsets.all(16),
-                              child: Text(
-                                message,
-                                textAlign: TextAlign.center,
-                                style: theme.textTheme.labelLarge!.copyWith(color: theme.colorScheme.error),
-                              ),
-                            ),
-                          ),
-                        );
-                      }
-                      if (!snapshot.hasData) {
-                        return const Center(child: SizedBox(width: 50, height: 50, child: CircularProgressIndicator()));
-                      }
-                      if (snapshot.data!.isEmpty && _searchedText != null) {
-                        return Padding(
-                          padding: const EdgeInsets.only(left: 16, right: 16, top: 80),
-                          child: SuggestSong(
-                            api: widget.api,
-                            failedSearch: _searchedText!,
-                          ),
-                        );
-                      }
-                      return ListView.builder(
-                        primary: true,
-                        padding: const EdgeInsets.only(top: 66),
-                        itemCount: snapshot.data!.length,
-                        itemBuilder: (context, index) {
-                          return SongCard(song: snapshot.data![index], api: widget.api);
-                        },
-                      );
-                    },
+            child: (_search != null)
+                ? PagedListView<String?, Song>(
+                    pagingController: _pagingController,
+                    primary: true,
+                    padding: const EdgeInsets.only(top: 66),
+                    builderDelegate: PagedChildBuilderDelegate<Song>(
+                      itemBuilder: (context, item, index) => SongCard(song: item, api: widget.api),
+                      firstPageErrorIndicatorBuilder: (context) => _errorMessage(context, _pagingController.error),
+                      firstPageProgressIndicatorBuilder: (context) =>
+                          const Center(child: SizedBox(width: 50, height: 50, child: CircularProgressIndicator())),
+                      noItemsFoundIndicatorBuilder: (context) => Padding(
+                        padding: const EdgeInsets.only(left: 16, right: 16, top: 14),
+                        child: SuggestSong(
+                          api: widget.api,
+                          failedSearch: _searchedText!,
+                        ),
+                      ),
+                    ),
                   )
                 : Padding(
                     padding: const EdgeInsets.only(top: 72),
@@ -164,7 +186,7 @@ class _SearchState extends State<Search> {
                                   onPressed: () {
                                     _controller.clear();
                                     setState(() {
-                                      _searchResults = null;
+                                      _search = null;
                                       _searchedText = null;
                                     });
                                   },
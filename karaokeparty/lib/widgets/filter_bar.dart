@@ -37,11 +37,11 @@ class FilterBar extends StatelessWidget {
                         ...(api.connectionCubit.state as WebSocketConnectedState)
                             .languages
                             .map((language) => CheckboxMenuButton(
-                                value: searchFilter.languages.contains(language),
+                                value: searchFilter.languages.contains(language.code),
                                 onChanged: (_) {
-                                  context.read<SearchFilterCubit>().toggleLanguage(language);
+                                  context.read<SearchFilterCubit>().toggleLanguage(language.code);
                                 },
-                                child: Text(language)))
+                                child: Text('${language.name} (${language.count})')))
                       ]
                     : const [],
                 builder: (context, controller, child) => IconButton(
@@ -126,6 +126,48 @@ class FilterBar extends StatelessWidget {
                     icon: const Icon(Icons.group)),
               ),
             ),
+            Tooltip(
+              message: context.t.search.searchFilterDifficultyTooltip,
+              child: MenuAnchor(
+                menuChildren: [
+                  RadioMenuButton(
+                      value: null,
+                      groupValue: searchFilter.difficulty,
+                      onChanged: (_) {
+                        context.read<SearchFilterCubit>().difficulty = null;
+                      },
+                      child: Text(context.t.search.searchFilterAllDifficulties)),
+                  const Divider(),
+                  ...context.t.search.searchFilterDifficulties.entries.map(
+                    (entry) => RadioMenuButton(
+                      value: entry.key,
+                      groupValue: searchFilter.difficulty,
+                      onChanged: (_) {
+                        context.read<SearchFilterCubit>().difficulty = entry.key;
+                      },
+                      child: Text(entry.value),
+                    ),
+                  ),
+                  const Divider(),
+                  CheckboxMenuButton(
+                      value: searchFilter.narrowRange,
+                      onChanged: (value) {
+                        context.read<SearchFilterCubit>().narrowRange = value ?? false;
+                      },
+                      child: Text(context.t.search.searchFilterNarrowRange)),
+                ],
+                builder: (context, controller, child) => IconButton(
+                    onPressed: () {
+                      if (controller.isOpen) {
+                        controller.close();
+                      } else {
+                        controller.open();
+                      }
+                    },
+                    isSelected: searchFilter.difficulty != null || searchFilter.narrowRange,
+                    icon: const Icon(Icons.speed)),
+              ),
+            ),
           ],
         );
       },
@@ -202,7 +202,7 @@ class SongCard extends StatelessWidget {
       );
 
   Image coverImageWidget() => Image.network(
-        '${serverHost.media}/${song.coverPath}',
+        '${serverHost.api}/cover/${song.id}?size=256',
         loadingBuilder: (BuildContext context, Widget child, ImageChunkEvent? loadingProgress) {
           if (loadingProgress == null) {
             return child;
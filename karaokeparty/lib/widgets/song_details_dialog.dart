@@ -95,7 +95,7 @@ class _SongDetailsDialogState extends State<SongDetailsDialog> {
   }
 
   Image coverImageWidget() => Image.network(
-        '${serverHost.media}/${widget.song.coverPath}',
+        '${serverHost.api}/cover/${widget.song.id}?size=512',
         fit: BoxFit.contain,
         loadingBuilder: (BuildContext context, Widget child, ImageChunkEvent? loadingProgress) {
           if (loadingProgress == null) {
@@ -30,7 +30,17 @@ class _SongPlayerState extends State<SongPlayer> {
     super.initState();
     WidgetsBinding.instance.addPostFrameCallback((_) {
       final player = widget.player;
-      player.setSourceUrl('${serverHost.media}/${widget.song.audioPath}');
+      if (widget.song.hasPreview) {
+        // The clip already starts at the right place.
+        player.setSourceUrl('${serverHost.api}/preview/${widget.song.id}');
+      } else {
+        player.setSourceUrl('${serverHost.media}/${widget.song.audioPath}').then((_) {
+          final previewStart = widget.song.previewStart;
+          if (previewStart != null && !disposed) {
+            player.seek(Duration(milliseconds: (previewStart * 1000).round()));
+          }
+        });
+      }
       _subscriptions.add(player.onDurationChanged.listen((newDuration) {
         if (!disposed) {
           setState(() {
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS song (
+    path BLOB UNIQUE NOT NULL,
+    title TEXT NOT NULL,
+    artist TEXT NOT NULL,
+    language TEXT,
+    year INTEGER,
+    duration REAL NOT NULL,
+    lyrics TEXT,
+    player_count INTEGER,
+    cover_path BLOB,
+    audio_path BLOB
+);
@@ -0,0 +1,7 @@
+ALTER TABLE song ADD COLUMN txt_mtime INTEGER;
+ALTER TABLE song ADD COLUMN txt_size INTEGER;
+ALTER TABLE song ADD COLUMN txt_hash TEXT;
+ALTER TABLE song ADD COLUMN audio_file BLOB;
+ALTER TABLE song ADD COLUMN audio_mtime INTEGER;
+ALTER TABLE song ADD COLUMN audio_size INTEGER;
+ALTER TABLE song ADD COLUMN audio_hash TEXT;
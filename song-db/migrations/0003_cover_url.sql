@@ -0,0 +1,3 @@
+ALTER TABLE song ADD COLUMN cover_url TEXT;
+-- Force a rescan to fill in the new column.
+UPDATE song SET txt_mtime = NULL;
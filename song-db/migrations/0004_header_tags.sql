@@ -0,0 +1,12 @@
+ALTER TABLE song ADD COLUMN genre TEXT;
+ALTER TABLE song ADD COLUMN edition TEXT;
+ALTER TABLE song ADD COLUMN creator TEXT;
+ALTER TABLE song ADD COLUMN preview_start REAL;
+ALTER TABLE song ADD COLUMN medley_start_beat INTEGER;
+ALTER TABLE song ADD COLUMN medley_end_beat INTEGER;
+ALTER TABLE song ADD COLUMN bpm REAL;
+ALTER TABLE song ADD COLUMN gap REAL;
+ALTER TABLE song ADD COLUMN video_path BLOB;
+ALTER TABLE song ADD COLUMN background_path BLOB;
+-- Force a rescan to fill in the new columns.
+UPDATE song SET txt_mtime = NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE song ADD COLUMN song_id TEXT;
+CREATE UNIQUE INDEX song_song_id ON song (song_id);
+-- Force a rescan to fill in the new column.
+UPDATE song SET txt_mtime = NULL;
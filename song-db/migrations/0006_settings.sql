@@ -0,0 +1,5 @@
+-- Settings of the last import, like the media root the stored paths are relative to.
+CREATE TABLE setting (
+    name TEXT PRIMARY KEY NOT NULL,
+    value
+);
@@ -0,0 +1,8 @@
+ALTER TABLE song ADD COLUMN lowest_pitch INTEGER;
+ALTER TABLE song ADD COLUMN highest_pitch INTEGER;
+ALTER TABLE song ADD COLUMN pitch_range INTEGER;
+ALTER TABLE song ADD COLUMN golden_share REAL;
+ALTER TABLE song ADD COLUMN freestyle_share REAL;
+ALTER TABLE song ADD COLUMN notes_per_second REAL;
+-- Force a rescan to fill in the new columns.
+UPDATE song SET txt_mtime = NULL;
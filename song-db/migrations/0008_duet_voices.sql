@@ -0,0 +1,6 @@
+ALTER TABLE song ADD COLUMN lyrics_p1 TEXT;
+ALTER TABLE song ADD COLUMN lyrics_p2 TEXT;
+ALTER TABLE song ADD COLUMN singer_p1 TEXT;
+ALTER TABLE song ADD COLUMN singer_p2 TEXT;
+-- Force a rescan to fill in the new columns.
+UPDATE song SET txt_mtime = NULL;
@@ -0,0 +1,2 @@
+-- Filled in by the importer's duplicate analysis after every import, so no rescan is needed.
+ALTER TABLE song ADD COLUMN duplicate_group INTEGER;
@@ -0,0 +1,4 @@
+ALTER TABLE song ADD COLUMN loudness REAL;
+ALTER TABLE song ADD COLUMN true_peak REAL;
+-- Force a rescan to fill in the new columns.
+UPDATE song SET txt_mtime = NULL;
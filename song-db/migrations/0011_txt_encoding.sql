@@ -0,0 +1,5 @@
+ALTER TABLE song ADD COLUMN txt_encoding TEXT;
+-- How the encoding was determined: bom, tag, utf8 or guessed.
+ALTER TABLE song ADD COLUMN txt_encoding_source TEXT;
+-- Force a rescan to decode all files with the new detection.
+UPDATE song SET txt_mtime = NULL;
@@ -0,0 +1,4 @@
+-- Comma separated ISO 639 codes, normalized from the free text in `language`.
+ALTER TABLE song ADD COLUMN language_codes TEXT;
+-- Force a rescan to fill in the new column.
+UPDATE song SET txt_mtime = NULL;
@@ -0,0 +1,4 @@
+-- When the song was first imported, as a Unix timestamp. Updates of the song keep it.
+ALTER TABLE song ADD COLUMN added_at INTEGER;
+-- Songs imported before this was tracked count as added now.
+UPDATE song SET added_at = CAST(strftime('%s', 'now') AS INTEGER);
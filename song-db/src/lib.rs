@@ -0,0 +1,163 @@
+use rusqlite::{Connection, OptionalExtension, Transaction};
+
+/// The schema migrations of the song database, in order. The index + 1 is the schema version
+/// after applying the migration. Never change a migration that was already released, add a new
+/// one instead.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/0001_initial.sql"),
+    include_str!("../migrations/0002_file_states.sql"),
+    include_str!("../migrations/0003_cover_url.sql"),
+    include_str!("../migrations/0004_header_tags.sql"),
+    include_str!("../migrations/0005_song_id.sql"),
+    include_str!("../migrations/0006_settings.sql"),
+    include_str!("../migrations/0007_vocal_metrics.sql"),
+    include_str!("../migrations/0008_duet_voices.sql"),
+    include_str!("../migrations/0009_duplicate_group.sql"),
+    include_str!("../migrations/0010_loudness.sql"),
+    include_str!("../migrations/0011_txt_encoding.sql"),
+    include_str!("../migrations/0012_language_codes.sql"),
+    include_str!("../migrations/0013_added_at.sql"),
+];
+
+/// The schema version this build reads and writes.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Returns the schema version of the database. Databases created before versioning was
+/// introduced are version 0.
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    let has_version_table = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+            (),
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !has_version_table {
+        return Ok(0);
+    }
+    Ok(conn
+        .query_row("SELECT version FROM schema_version", (), |row| row.get(0))
+        .optional()?
+        .unwrap_or(0))
+}
+
+/// Brings the database up to [`SCHEMA_VERSION`], creating it if it's empty.
+pub fn migrate(conn: &mut Connection) -> anyhow::Result<()> {
+    let tx = conn.transaction()?;
+    migrate_in_transaction(&tx)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Like [`migrate`], but leaves committing to the caller, so that the migrations can be rolled
+/// back together with its other changes.
+pub fn migrate_in_transaction(tx: &Transaction) -> anyhow::Result<()> {
+    let version = schema_version(tx)?;
+    if version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "The song database has schema version {version}, but this importer only supports up to version {SCHEMA_VERSION}. Please update the importer."
+        );
+    }
+
+    for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        tx.execute_batch(migration)?;
+        tx.execute_batch(
+            "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL); DELETE FROM schema_version;",
+        )?;
+        tx.execute(
+            "INSERT INTO schema_version (version) VALUES (?1)",
+            (idx as u32 + 1,),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Makes sure that the database has exactly the schema version this build expects.
+pub fn check_schema_version(conn: &Connection) -> anyhow::Result<()> {
+    let version = schema_version(conn)?;
+    if version < SCHEMA_VERSION {
+        anyhow::bail!(
+            "The song database has schema version {version}, but version {SCHEMA_VERSION} is required. Please rerun the importer on it."
+        );
+    }
+    if version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "The song database has schema version {version}, but only version {SCHEMA_VERSION} is supported. Please update the server."
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song_columns(conn: &Connection) -> Vec<String> {
+        conn.prepare("SELECT name FROM pragma_table_info('song')")
+            .unwrap()
+            .query_map((), |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn creates_empty_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        migrate(&mut conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        check_schema_version(&conn).unwrap();
+    }
+
+    #[test]
+    fn migrates_original_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // The schema the importer created before the migrations were introduced.
+        conn.execute_batch(
+            "CREATE TABLE song (
+                path BLOB UNIQUE NOT NULL,
+                title TEXT NOT NULL,
+                artist TEXT NOT NULL,
+                language TEXT,
+                year INTEGER,
+                duration REAL NOT NULL,
+                lyrics TEXT,
+                player_count INTEGER,
+                cover_path BLOB,
+                audio_path BLOB
+            );
+            INSERT INTO song (path, title, artist, duration) VALUES (x'612e747874', 'Title', 'Artist', 180.0);",
+        )
+        .unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        migrate(&mut conn).unwrap();
+        assert!(song_columns(&conn).contains(&"song_id".to_owned()));
+        let title: String = conn
+            .query_row("SELECT title FROM song", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(title, "Title");
+        check_schema_version(&conn).unwrap();
+    }
+
+    #[test]
+    fn rolls_back_migrations() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        let tx = conn.transaction().unwrap();
+        migrate_in_transaction(&tx).unwrap();
+        assert_eq!(schema_version(&tx).unwrap(), SCHEMA_VERSION);
+        tx.rollback().unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        assert!(!song_columns(&conn).contains(&"song_id".to_owned()));
+    }
+
+    #[test]
+    fn rejects_outdated_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        assert!(check_schema_version(&conn).is_err());
+    }
+}